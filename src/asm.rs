@@ -0,0 +1,704 @@
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+// programs are assembled to run from the usual chip8 start address
+const ORIGIN: u16 = 0x200;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    InvalidOperands(String), // the operands don't fit the mnemonic
+    InvalidNumber(String),
+    ValueOutOfRange(String),
+    UnknownLabel(String),
+    DuplicateLabel(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize, // 1-based line number in the source
+    pub kind: AsmErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand<'a> {
+    V(u16),         // V0-VF
+    I,              // I
+    IndirectI,      // [I]
+    DelayTimer,     // DT
+    SoundTimer,     // ST
+    Key,            // K
+    Font,           // F
+    Bcd,            // B
+    Value(&'a str), // a number or a label, resolved in the second pass
+}
+
+// a source line split into its parts
+struct Line<'a> {
+    number: usize,
+    mnemonic: String,
+    operands: Vec<Operand<'a>>,
+}
+
+// assemble CHIP-8 mnemonics (Cowgod's syntax) into rom bytes loaded at 0x200
+//
+//   start:
+//       LD V0, 0x0A     ; comments start with a semicolon
+//   loop: ADD V0, 1     ; labels can be used before they are defined
+//       SE V0, 20
+//       JP loop
+//
+// numbers are decimal, hex (0x, # or $ prefix) or binary (0b prefix)
+// besides the standard instructions, DB emits bytes and DW emits big-endian words
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut labels = BTreeMap::new();
+    let mut lines = Vec::new();
+    let mut address = ORIGIN;
+
+    // first pass: split the lines up, find where every label points
+    for (i, text) in source.lines().enumerate() {
+        let number = i + 1;
+        let error = |kind| AsmError { line: number, kind };
+
+        let mut rest = text.split(';').next().unwrap_or("").trim();
+
+        // any number of labels can prefix an instruction
+        while let Some((label, after)) = rest.split_once(':') {
+            let label = label.trim();
+            if label.is_empty() || !label.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(error(AsmErrorKind::InvalidOperands(
+                    text.trim().to_string(),
+                )));
+            }
+
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(error(AsmErrorKind::DuplicateLabel(label.to_string())));
+            }
+
+            rest = after.trim();
+        }
+
+        if rest.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match rest.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic, operands.trim()),
+            None => (rest, ""),
+        };
+
+        let operands: Vec<Operand> = if operands.is_empty() {
+            Vec::new()
+        } else {
+            operands
+                .split(',')
+                .map(|o| parse_operand(o.trim()))
+                .collect()
+        };
+
+        let line = Line {
+            number,
+            mnemonic: mnemonic.to_ascii_uppercase(),
+            operands,
+        };
+
+        address += match line.mnemonic.as_str() {
+            "DB" => line.operands.len() as u16,
+            _ => 2, // every instruction and DW is two bytes
+        };
+
+        lines.push(line);
+    }
+
+    // second pass: encode everything now that all labels are known
+    let mut rom = Vec::new();
+    for line in &lines {
+        match line.mnemonic.as_str() {
+            "DB" => {
+                for operand in &line.operands {
+                    rom.push(value(line, operand, &labels, 0xFF)? as u8);
+                }
+            }
+            _ => {
+                let opcode = encode(line, &labels)?;
+                rom.extend_from_slice(&opcode.to_be_bytes());
+            }
+        }
+    }
+
+    Ok(rom)
+}
+
+fn parse_operand(text: &str) -> Operand<'_> {
+    match text.to_ascii_uppercase().as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "B" => Operand::Bcd,
+        upper => {
+            let register = upper
+                .strip_prefix('V')
+                .filter(|digit| digit.len() == 1)
+                .and_then(|digit| u16::from_str_radix(digit, 16).ok());
+
+            match register {
+                Some(reg) => Operand::V(reg),
+                None => Operand::Value(text),
+            }
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    let lower = text.to_ascii_lowercase();
+
+    if let Some(hex) = lower
+        .strip_prefix("0x")
+        .or_else(|| lower.strip_prefix('#'))
+        .or_else(|| lower.strip_prefix('$'))
+    {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+// resolve a number or label operand, checking it fits in `max`
+fn value(
+    line: &Line,
+    operand: &Operand,
+    labels: &BTreeMap<String, u16>,
+    max: u32,
+) -> Result<u16, AsmError> {
+    let error = |kind| AsmError {
+        line: line.number,
+        kind,
+    };
+
+    let Operand::Value(text) = operand else {
+        return Err(error(AsmErrorKind::InvalidOperands(line.mnemonic.clone())));
+    };
+
+    let number = if text.starts_with(|c: char| c.is_ascii_digit() || c == '#' || c == '$') {
+        parse_number(text).ok_or_else(|| error(AsmErrorKind::InvalidNumber(text.to_string())))?
+    } else {
+        *labels
+            .get(*text)
+            .ok_or_else(|| error(AsmErrorKind::UnknownLabel(text.to_string())))? as u32
+    };
+
+    if number > max {
+        return Err(error(AsmErrorKind::ValueOutOfRange(text.to_string())));
+    }
+
+    Ok(number as u16)
+}
+
+fn encode(line: &Line, labels: &BTreeMap<String, u16>) -> Result<u16, AsmError> {
+    use Operand::*;
+
+    let addr = |operand| value(line, operand, labels, 0xFFF);
+    let byte = |operand| value(line, operand, labels, 0xFF);
+    let xy = |x: u16, y: u16| (x << 8) | (y << 4);
+
+    let opcode = match (line.mnemonic.as_str(), line.operands.as_slice()) {
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("SYS", [nnn]) => addr(nnn)?,
+        ("JP", [V(0), nnn]) => 0xB000 | addr(nnn)?,
+        ("JP", [nnn]) => 0x1000 | addr(nnn)?,
+        ("CALL", [nnn]) => 0x2000 | addr(nnn)?,
+        ("SE", [V(x), V(y)]) => 0x5000 | xy(*x, *y),
+        ("SE", [V(x), kk]) => 0x3000 | (x << 8) | byte(kk)?,
+        ("SNE", [V(x), V(y)]) => 0x9000 | xy(*x, *y),
+        ("SNE", [V(x), kk]) => 0x4000 | (x << 8) | byte(kk)?,
+        ("LD", [V(x), V(y)]) => 0x8000 | xy(*x, *y),
+        ("LD", [V(x), DelayTimer]) => 0xF007 | (x << 8),
+        ("LD", [V(x), Key]) => 0xF00A | (x << 8),
+        ("LD", [V(x), IndirectI]) => 0xF065 | (x << 8),
+        ("LD", [V(x), kk]) => 0x6000 | (x << 8) | byte(kk)?,
+        ("LD", [I, nnn]) => 0xA000 | addr(nnn)?,
+        ("LD", [DelayTimer, V(x)]) => 0xF015 | (x << 8),
+        ("LD", [SoundTimer, V(x)]) => 0xF018 | (x << 8),
+        ("LD", [Font, V(x)]) => 0xF029 | (x << 8),
+        ("LD", [Bcd, V(x)]) => 0xF033 | (x << 8),
+        ("LD", [IndirectI, V(x)]) => 0xF055 | (x << 8),
+        ("ADD", [V(x), V(y)]) => 0x8004 | xy(*x, *y),
+        ("ADD", [V(x), kk]) => 0x7000 | (x << 8) | byte(kk)?,
+        ("ADD", [I, V(x)]) => 0xF01E | (x << 8),
+        ("OR", [V(x), V(y)]) => 0x8001 | xy(*x, *y),
+        ("AND", [V(x), V(y)]) => 0x8002 | xy(*x, *y),
+        ("XOR", [V(x), V(y)]) => 0x8003 | xy(*x, *y),
+        ("SUB", [V(x), V(y)]) => 0x8005 | xy(*x, *y),
+        ("SHR", [V(x)]) => 0x8006 | xy(*x, *x),
+        ("SHR", [V(x), V(y)]) => 0x8006 | xy(*x, *y),
+        ("SUBN", [V(x), V(y)]) => 0x8007 | xy(*x, *y),
+        ("SHL", [V(x)]) => 0x800E | xy(*x, *x),
+        ("SHL", [V(x), V(y)]) => 0x800E | xy(*x, *y),
+        ("RND", [V(x), kk]) => 0xC000 | (x << 8) | byte(kk)?,
+        ("DRW", [V(x), V(y), n]) => 0xD000 | xy(*x, *y) | value(line, n, labels, 0xF)?,
+        ("SKP", [V(x)]) => 0xE09E | (x << 8),
+        ("SKNP", [V(x)]) => 0xE0A1 | (x << 8),
+        ("DW", [word]) => value(line, word, labels, 0xFFFF)?,
+        (
+            "CLS" | "RET" | "SYS" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR" | "AND"
+            | "XOR" | "SUB" | "SHR" | "SUBN" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" | "DW",
+            _,
+        ) => {
+            return Err(AsmError {
+                line: line.number,
+                kind: AsmErrorKind::InvalidOperands(line.mnemonic.clone()),
+            })
+        }
+        (mnemonic, _) => {
+            return Err(AsmError {
+                line: line.number,
+                kind: AsmErrorKind::UnknownMnemonic(mnemonic.to_string()),
+            })
+        }
+    };
+
+    Ok(opcode)
+}
+
+// what the self test rom leaves in VE once every check passed (VD is the number of the check
+// that failed otherwise, and VE is FAILED)
+pub const SELF_TEST_PASSED: u8 = 0xAA;
+pub const SELF_TEST_FAILED: u8 = 0xFF;
+// the self test waits for a key with Fx0A, hold this one down the whole time it runs
+pub const SELF_TEST_KEY: u8 = 0xB;
+
+// every check does something and skips over the JP fail if it got the right answer, VD counts
+// the checks. the results don't depend on the quirks:
+// - shifts shift a register into itself
+// - I is set again before every load and store
+// - VF is only checked where every interpreter agrees
+// - JP V0 has V2/V3 (what Bxnn would use for a target in 0x200-0x3FF) equal to V0
+// the SUPER-CHIP and XO-CHIP instructions aren't in the assembler's syntax, they're DW'd in
+const SELF_TEST_SOURCE: &str = "
+        LD VD, 0
+        LD VE, 0
+
+        ADD VD, 1           ; 6xkk, 3xkk, 4xkk
+        LD V0, 5
+        SE V0, 5
+        JP fail
+        SNE V0, 6
+        JP fail
+        ADD VD, 1           ; 8xy0, 5xy0, 9xy0
+        LD V1, V0
+        SE V1, V0
+        JP fail
+        LD V2, 9
+        SNE V1, V2
+        JP fail
+
+        ADD VD, 1           ; 7xkk wraps
+        LD V0, 0xFF
+        ADD V0, 2
+        SE V0, 1
+        JP fail
+
+        ADD VD, 1           ; 8xy1, 8xy2, 8xy3
+        LD V0, 0b1100
+        LD V1, 0b1010
+        OR V0, V1
+        SE V0, 0b1110
+        JP fail
+        LD V0, 0b1100
+        AND V0, V1
+        SE V0, 0b1000
+        JP fail
+        LD V0, 0b1100
+        XOR V0, V1
+        SE V0, 0b0110
+        JP fail
+
+        ADD VD, 1           ; 8xy4 with and without a carry
+        LD V0, 0xFF
+        LD V1, 2
+        ADD V0, V1
+        SE VF, 1
+        JP fail
+        SE V0, 1
+        JP fail
+        ADD V0, V1
+        SE VF, 0
+        JP fail
+        SE V0, 3
+        JP fail
+
+        ADD VD, 1           ; 8xy5 and 8xy7 borrow
+        LD V0, 5
+        LD V1, 7
+        SUB V0, V1
+        SE VF, 0
+        JP fail
+        SE V0, 0xFE
+        JP fail
+        LD V0, 5
+        SUBN V0, V1
+        SE VF, 1
+        JP fail
+        SE V0, 2
+        JP fail
+
+        ADD VD, 1           ; 8xy6 and 8xyE
+        LD V0, 5
+        SHR V0
+        SE VF, 1
+        JP fail
+        SE V0, 2
+        JP fail
+        LD V0, 0x81
+        SHL V0
+        SE VF, 1
+        JP fail
+        SE V0, 2
+        JP fail
+
+        ADD VD, 1           ; 2nnn and 00EE
+        LD V5, 0
+        CALL subroutine
+        SE V5, 1
+        JP fail
+
+        ADD VD, 1           ; Bnnn
+        LD V0, 2
+        LD V2, 2
+        LD V3, 2
+        JP V0, before_jumped
+        JP fail
+before_jumped:
+        JP fail             ; V0 + before_jumped lands past this
+
+        ADD VD, 1           ; Annn, Fx55, Fx65
+        LD V0, 1
+        LD V1, 2
+        LD V2, 3
+        LD I, scratch
+        LD [I], V2
+        LD V0, 0
+        LD V1, 0
+        LD V2, 0
+        LD I, scratch
+        LD V2, [I]
+        SE V0, 1
+        JP fail
+        SE V2, 3
+        JP fail
+
+        ADD VD, 1           ; Fx1E
+        LD I, scratch
+        LD V0, 2
+        ADD I, V0
+        LD V0, [I]
+        SE V0, 3
+        JP fail
+
+        ADD VD, 1           ; Fx33
+        LD V0, 234
+        LD I, scratch
+        LD B, V0
+        LD I, scratch
+        LD V2, [I]
+        SE V0, 2
+        JP fail
+        SE V1, 3
+        JP fail
+        SE V2, 4
+        JP fail
+
+        ADD VD, 1           ; Fx29 points at the font
+        LD V0, 0
+        LD F, V0
+        LD V0, [I]
+        SE V0, 0xF0
+        JP fail
+
+        ADD VD, 1           ; Fx15, Fx07, Fx18
+        LD V0, 200
+        LD DT, V0
+        LD V1, DT
+        SNE V1, 0
+        JP fail
+        LD ST, V0
+        LD V0, 0
+        LD ST, V0
+
+        ADD VD, 1           ; Cxkk keeps within its mask, whatever the rng says
+        RND V0, 0
+        SE V0, 0
+        JP fail
+        RND V1, 0x0F
+        LD V2, 0xF0
+        AND V2, V1
+        SE V2, 0
+        JP fail
+
+        ADD VD, 1           ; Ex9E and ExA1 with nothing pressed
+        LD V0, 1
+        SKP V0
+        JP not_pressed
+        JP fail
+not_pressed:
+        SKNP V0
+        JP fail
+
+        ADD VD, 1           ; Fx0A gets SELF_TEST_KEY, which Ex9E sees held
+        LD V0, K
+        SE V0, 0xB
+        JP fail
+        SKNP V0
+        JP held
+        JP fail
+held:
+        SKP V0
+        JP fail
+
+        ADD VD, 1           ; 00E0 and DXYN collisions
+        CLS
+        LD I, dot
+        LD V0, 8
+        LD V1, 8
+        DRW V0, V1, 1
+        SE VF, 0
+        JP fail
+        DRW V0, V1, 1
+        SE VF, 1
+        JP fail
+        DRW V0, V1, 1
+        CLS
+        DRW V0, V1, 1
+        SE VF, 0
+        JP fail
+
+        ADD VD, 1           ; 00FB scrolls the dot right 4
+        DW 0x00FB
+        LD V2, 12
+        DRW V2, V1, 1
+        SE VF, 1
+        JP fail
+
+        ADD VD, 1           ; 00FC scrolls left 4
+        DRW V0, V1, 1
+        DW 0x00FC
+        LD V2, 4
+        DRW V2, V1, 1
+        SE VF, 1
+        JP fail
+
+        ADD VD, 1           ; 00C1 scrolls down 1, 00D1 up 1
+        DRW V0, V1, 1
+        DW 0x00C1
+        LD V2, 9
+        DRW V0, V2, 1
+        SE VF, 1
+        JP fail
+        DRW V0, V1, 1
+        DW 0x00D1
+        LD V2, 7
+        DRW V0, V2, 1
+        SE VF, 1
+        JP fail
+
+        ADD VD, 1           ; 00FF and 00FE, with a sprite only high resolution has room for
+        DW 0x00FF
+        CLS
+        LD V0, 100
+        LD V1, 40
+        DRW V0, V1, 1
+        DW 0x00FE
+        CLS
+        DRW V0, V1, 1       ; past the edge now, wrapped or clipped it can't collide
+        SE VF, 0
+        JP fail
+
+        ADD VD, 1           ; Dxy0 draws 16x16
+        CLS
+        LD I, block
+        LD V0, 8
+        LD V1, 8
+        DRW V0, V1, 0
+        SE VF, 0
+        JP fail
+        LD I, dot
+        LD V2, 23
+        DRW V2, V2, 1       ; the bottom right corner of the block
+        SE VF, 1
+        JP fail
+        LD V2, 24
+        DRW V2, V1, 1       ; just right of it
+        SE VF, 0
+        JP fail
+        CLS
+
+        ADD VD, 1           ; Fx75 and Fx85
+        LD V0, 7
+        DW 0xF075
+        LD V0, 0
+        DW 0xF085
+        SE V0, 7
+        JP fail
+
+        ADD VD, 1           ; Fx30 points at the big font
+        LD V0, 0
+        DW 0xF030
+        LD V0, [I]
+        SE V0, 0x3C
+        JP fail
+
+        ADD VD, 1           ; 5xy2 and 5xy3
+        LD V0, 4
+        LD V1, 5
+        LD I, scratch
+        DW 0x5012
+        LD V0, 0
+        LD V1, 0
+        DW 0x5013
+        SE V1, 5
+        JP fail
+
+        ADD VD, 1           ; F000 nnnn
+        DW 0xF000
+        DW scratch
+        LD V0, [I]
+        SE V0, 4
+        JP fail
+
+        ADD VD, 1           ; Fn01 and F002
+        DW 0xF101
+        LD I, scratch
+        DW 0xF002
+
+        SYS 0x123           ; 0nnn is skipped
+        LD VE, 0xAA         ; SELF_TEST_PASSED
+        DW 0x00FD           ; 00FD ends the program
+
+fail:
+        LD VE, 0xFF         ; SELF_TEST_FAILED
+stop:
+        JP stop
+
+subroutine:
+        LD V5, 1
+        RET
+
+dot:
+        DB 0x80
+block:
+        DB 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF
+        DB 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF
+scratch:
+        DB 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+";
+
+// a rom running every instruction this emulator implements once and checking what it did, see
+// SELF_TEST_PASSED. load the fonts before running it, it reads them, and hold SELF_TEST_KEY
+// (the source is fixed, so an error is a bug in it or in the assembler)
+pub fn self_test_rom() -> Result<Vec<u8>, AsmError> {
+    assemble(SELF_TEST_SOURCE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rng::SeededRng, Quirks, CHIP8};
+    use alloc::boxed::Box;
+
+    // run the self test to its end (or a cycle limit) with the given quirks
+    fn run_self_test(quirks: Quirks, seed: u64) -> CHIP8 {
+        let mut chip8 = CHIP8::builder()
+            .quirks(quirks)
+            .rng(Box::new(SeededRng::new(seed)))
+            .build();
+        chip8.load_fonts();
+        chip8.load_rom_bytes(&self_test_rom().unwrap()).unwrap();
+        chip8.keypad[SELF_TEST_KEY as usize] = true;
+
+        for _ in 0..2000 {
+            if chip8.is_halted() {
+                break;
+            }
+            chip8.cycle().unwrap();
+        }
+
+        chip8
+    }
+
+    #[test]
+    fn self_test_passes_with_every_preset() {
+        for quirks in [
+            Quirks::default(),
+            Quirks::cosmac_vip(),
+            Quirks::super_chip(),
+            Quirks::xo_chip(),
+        ] {
+            for seed in 0..4 {
+                let chip8 = run_self_test(quirks, seed);
+                assert!(chip8.is_halted(), "{:?}\n{}", quirks, chip8.dump_state());
+                assert_eq!(
+                    chip8.vregister[0xE], SELF_TEST_PASSED,
+                    "check {} failed with {:?}",
+                    chip8.vregister[0xD], quirks
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn assembles_a_labeled_loop() {
+        let source = "
+start:  LD V0, 0x0A     ; count up to 20
+loop:   ADD V0, 1
+        SE V0, 20
+        JP loop
+        JP end
+end:    DB 1, 2
+        DW 0x1234
+        LD [I], V3
+        DRW V0, V1, 5
+        JP start
+";
+        let rom = assemble(source).unwrap();
+
+        assert_eq!(
+            rom,
+            [
+                0x60, 0x0A, 0x70, 0x01, 0x30, 20, 0x12, 0x02, 0x12, 0x0A, 1, 2, 0x12, 0x34, 0xF3,
+                0x55, 0xD0, 0x15, 0x12, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_the_failing_line() {
+        assert_eq!(
+            assemble("CLS\nFOO V1"),
+            Err(AsmError {
+                line: 2,
+                kind: AsmErrorKind::UnknownMnemonic("FOO".to_string()),
+            })
+        );
+        assert_eq!(
+            assemble("JP nowhere").unwrap_err().kind,
+            AsmErrorKind::UnknownLabel("nowhere".to_string())
+        );
+        assert_eq!(
+            assemble("LD V0, 256").unwrap_err().kind,
+            AsmErrorKind::ValueOutOfRange("256".to_string())
+        );
+        assert!(matches!(
+            assemble("a:\na:").unwrap_err().kind,
+            AsmErrorKind::DuplicateLabel(_)
+        ));
+    }
+}
@@ -0,0 +1,175 @@
+// terminal frontend, handy over ssh: cargo run --bin chip8-tui --features tui -- <rom>
+use std::io::{self, Stdout, Write};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use chip8::{chip8::RomError, frontend, frontend::DEFAULT_INSTRUCTIONS_PER_FRAME, Frontend, CHIP8};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute, queue, terminal,
+};
+
+// terminals only report key presses (and repeats), not releases, so a key counts as held
+// for this many frames after its last press
+const KEY_HOLD_FRAMES: u8 = 8;
+
+const FRAME_TIME: Duration = Duration::from_micros(16_667); // 60Hz
+
+struct TuiFrontend {
+    stdout: Stdout,
+    held: [u8; 16], // frames left before each key counts as released
+    closing: bool,
+    clear: bool, // the terminal was resized, wipe what was drawn at the old position
+    next_frame: Instant,
+}
+
+impl TuiFrontend {
+    fn new() -> io::Result<Self> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        Ok(Self {
+            stdout,
+            held: [0; 16],
+            closing: false,
+            clear: true,
+            next_frame: Instant::now(),
+        })
+    }
+
+    fn draw(&mut self, display: &[u8], width: usize, height: usize) -> io::Result<()> {
+        // two display rows share one terminal row, the top pixel is the upper half of the cell
+        let rows = height.div_ceil(2);
+
+        // keep the display centered, it just gets cut off if the terminal is too small
+        let (columns, lines) = terminal::size()?;
+        let left = (columns as usize).saturating_sub(width) / 2;
+        let top = (lines as usize).saturating_sub(rows) / 2;
+
+        if self.clear {
+            queue!(self.stdout, terminal::Clear(terminal::ClearType::All))?;
+            self.clear = false;
+        }
+
+        for row in 0..rows.min(lines as usize) {
+            let mut line = String::with_capacity(width * 3);
+            for x in 0..width.min(columns as usize) {
+                let lit = |y: usize| y < height && display[y * width + x] != 0;
+                line.push(match (lit(row * 2), lit(row * 2 + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+
+            queue!(self.stdout, cursor::MoveTo(left as u16, (top + row) as u16))?;
+            self.stdout.write_all(line.as_bytes())?;
+        }
+
+        self.stdout.flush()
+    }
+}
+
+impl Frontend for TuiFrontend {
+    fn present(&mut self, display: &[u8], width: usize, height: usize) {
+        // nothing sensible to do if the terminal went away, just stop
+        if self.draw(display, width, height).is_err() {
+            self.closing = true;
+        }
+
+        // the terminal doesn't pace anything for us, wait out the rest of the frame
+        let now = Instant::now();
+        if self.next_frame > now {
+            std::thread::sleep(self.next_frame - now);
+        }
+        self.next_frame = self.next_frame.max(now) + FRAME_TIME;
+    }
+
+    fn poll_keys(&mut self) -> [bool; 16] {
+        for held in self.held.iter_mut() {
+            *held = held.saturating_sub(1);
+        }
+
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => match key.code {
+                    KeyCode::Esc => self.closing = true,
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.closing = true
+                    }
+                    // the same layout as the window frontend: 0-9 and A-F
+                    KeyCode::Char(c) => {
+                        if let Some(digit) = c.to_digit(16) {
+                            self.held[digit as usize] = KEY_HOLD_FRAMES;
+                        }
+                    }
+                    _ => {}
+                },
+                Ok(Event::Resize(_, _)) => self.clear = true,
+                Ok(_) => {}
+                Err(_) => self.closing = true,
+            }
+        }
+
+        self.held.map(|frames| frames > 0)
+    }
+
+    fn should_close(&self) -> bool {
+        self.closing
+    }
+}
+
+// put the terminal back however the frontend goes away
+impl Drop for TuiFrontend {
+    fn drop(&mut self) {
+        let _ = execute!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// exit codes: 1 when the emulation (or the terminal) fails, 2 without a rom, 3 when the rom can't
+// be read and 4 when it doesn't fit in memory. the rom errors match the window frontend's, but
+// that one runs a demo when it isn't given a rom and uses 2 for a bad command line instead
+const EXIT_USAGE: u8 = 2;
+const EXIT_UNREADABLE_ROM: u8 = 3;
+const EXIT_ROM_TOO_LARGE: u8 = 4;
+
+fn main() -> ExitCode {
+    let Some(game) = std::env::args().nth(1) else {
+        eprintln!("usage: chip8-tui <rom>");
+        return ExitCode::from(EXIT_USAGE);
+    };
+
+    // the rom's quirks (and for SUPER-CHIP roms the variant) are picked the same way as in the window
+    let mut chip8 = CHIP8::new();
+    if let Err(e) = chip8.load_game(&game) {
+        eprintln!("couldn't load {}: {:?}", game, e);
+        return ExitCode::from(match e {
+            RomError::Io(_) => EXIT_UNREADABLE_ROM,
+            RomError::TooLarge(_) | RomError::OutOfBounds(_) => EXIT_ROM_TOO_LARGE,
+        });
+    }
+
+    let mut tui = match TuiFrontend::new() {
+        Ok(tui) => tui,
+        Err(e) => {
+            eprintln!("couldn't set up the terminal: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = frontend::run(&mut chip8, &mut tui, DEFAULT_INSTRUCTIONS_PER_FRAME);
+
+    // restore the terminal before printing anything
+    drop(tui);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("emulation stopped: {:?}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,7 +1,325 @@
-use std::{fs, thread::sleep, time::Duration};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeSet, VecDeque},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::fmt::Write;
+#[cfg(feature = "std")]
+use std::{fs, io, time::Instant};
 
+#[cfg(feature = "std")]
+use crate::frontend::{scale_into_palette, DEFAULT_PALETTE};
+use crate::{
+    instruction::{cycle_cost, decode, Instruction},
+    quirks::{detect_variant, known_rom, Quirks, Variant},
+    rng,
+    rng::{MachineRng, Rng},
+};
+
+// the original machines had 4KB of memory
+pub const MEMORY_SIZE: usize = 4096;
+// XO-CHIP programs can address up to 64KB
+pub const MAX_MEMORY_SIZE: usize = 0x10000;
+
+// the display size in pixels
+pub const CHIP8_WIDTH: usize = 64;
+pub const CHIP8_HEIGHT: usize = 32;
+// and in the SUPER-CHIP high resolution mode (00FF)
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+// the first 512 bytes were reserved for the interpreter on the original machines
+const INTERPRETER_END: u16 = 0x200;
+
+// where load_fonts puts the 5-byte hex digits and, right after them, the SUPER-CHIP 10-byte digits
+const FONT_ADDRESS: usize = 0x50;
+const BIG_FONT_ADDRESS: usize = 0xA0;
+
+// the standard 4x5 hex digits (0-F, 5 bytes each) that load_fonts puts at 0x50
+pub const FONT_SET: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemError {
+    OutOfBounds(u16), // the address is past the end of memory
+    Protected(u16),   // the address is inside the protected interpreter region
+}
+
+// why a cheat code couldn't be applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatError {
+    Malformed,       // not an AAA:VV pair of hex numbers
+    OutsideRom(u16), // the address isn't part of the loaded rom
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepError {
+    PcOutOfBounds(u16),          // the program counter points outside of memory
+    StackOverflow(u16),          // a call was made with the stack full (address of the call)
+    StackUnderflow(u16),         // a return was made with an empty stack (address of the return)
+    MemoryOutOfBounds(u16),      // an instruction tried to access memory past the end
+    UnsupportedMachineCall(u16), // a 0nnn machine code call with strict_machine_calls set (address of the call)
+    ProtectedWrite(u16), // an instruction wrote below 0x200 (fonts, interpreter) with strict_memory set
+    OutOfBoundsJump(u16), // Bnnn computed a target outside of memory (the target)
+    UnsupportedInVariant(u16), // the instruction doesn't exist in the selected variant (its address)
+    OutOfBoundsSpriteRead(u16), // DXYN's sprite data runs past the end of memory (I)
+    UnknownOpcode(u16), // an opcode this emulator doesn't know, with the Error policy (its address)
+}
+
+// what an opcode this emulator doesn't know does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnknownOpcodePolicy {
+    Halt, // stop the program on it, like it jumped to itself
+    Skip, // carry on with the next instruction
+    #[default]
+    Error, // StepError::UnknownOpcode
+}
+
+// what happened when an instruction was executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Continue,      // the instruction ran normally
+    WaitingForKey, // Fx0A found no key pressed, PC stays on it so it runs again
+    Halted,        // the program jumped to itself
+}
+
+// why run_until_break or run_budget stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(u16),
+    Error(StepError),
+    CycleLimit,
+    Deadline,      // run_budget ran out of time
+    Halted,        // the program has ended, running more won't do anything
+    WaitingForKey, // Fx0A is waiting, nothing happens until the keypad changes
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunResult {
+    pub cycles: usize, // how many cycles were executed
+    pub reason: StopReason,
+}
+
+// everything a debugger shows, read in one go so it can't change halfway through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MachineSnapshot {
+    pub vregister: [u8; 16],
+    pub index_register: u16,
+    pub program_counter: u16,
+    pub stack_pointer: u8,
+    pub stack: [u16; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub instructions: u64, // instructions executed since the machine was created or reset
+    pub machine_cycles: u64,
+    pub halted: bool,
+    pub waiting_for_key: bool, // the next instruction is Fx0A
+    pub hires: bool,
+}
+
+// the first thing diff_run found different between its two machines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difference {
+    Outcome, // one machine failed, halted or waited for a key and the other didn't
+    ProgramCounter,
+    Register(usize), // V0-VF
+    IndexRegister,
+    Stack, // the stack pointer or a return address
+    Timers,
+    Memory(u16),    // the first differing address
+    Resolution,     // one machine is in high resolution and the other isn't
+    Display(usize), // the first differing pixel, row by row
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DivergenceReport {
+    pub cycle: usize, // how many instructions both machines ran, the last one made the difference
+    pub pc: u16,      // the address of that instruction
+    pub difference: Difference,
+}
+
+// Fx33 or Fx55 wrote over an instruction that had already run, see enable_self_modify_checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeOverwrite {
+    pub pc: u16,   // the instruction doing the write
+    pub addr: u16, // the executed byte it overwrote
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewindError {
+    Disabled, // enable_rewind was never called
+    Empty,    // no more history to step back into
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    #[cfg(feature = "std")]
+    Io(io::Error), // the rom file couldn't be read
+    TooLarge(usize), // the data (size in bytes) doesn't fit in memory after its load address
+    OutOfBounds(u16), // the load address is past the end of memory
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for RomError {
+    fn from(e: io::Error) -> Self {
+        RomError::Io(e)
+    }
+}
+
+// instruction categories counted by the profiler: the 35 standard chip8 instructions and everything else
+pub const OPCODE_CATEGORIES: [&str; 36] = [
+    "SYS nnn",
+    "CLS",
+    "RET",
+    "JP nnn",
+    "CALL nnn",
+    "SE Vx, kk",
+    "SNE Vx, kk",
+    "SE Vx, Vy",
+    "LD Vx, kk",
+    "ADD Vx, kk",
+    "LD Vx, Vy",
+    "OR Vx, Vy",
+    "AND Vx, Vy",
+    "XOR Vx, Vy",
+    "ADD Vx, Vy",
+    "SUB Vx, Vy",
+    "SHR Vx",
+    "SUBN Vx, Vy",
+    "SHL Vx",
+    "SNE Vx, Vy",
+    "LD I, nnn",
+    "JP V0, nnn",
+    "RND Vx, kk",
+    "DRW Vx, Vy, n",
+    "SKP Vx",
+    "SKNP Vx",
+    "LD Vx, DT",
+    "LD Vx, K",
+    "LD DT, Vx",
+    "LD ST, Vx",
+    "ADD I, Vx",
+    "LD F, Vx",
+    "LD B, Vx",
+    "LD [I], Vx",
+    "LD Vx, [I]",
+    "other",
+];
+
+// index into OPCODE_CATEGORIES
+fn opcode_category(opcode: u16) -> usize {
+    const OTHER: usize = 35;
+
+    match (opcode & 0xF000, opcode & 0x000F, opcode & 0x00FF) {
+        _ if opcode == 0x00E0 => 1,
+        _ if opcode == 0x00EE => 2,
+        (0x0000, _, _) => 0,
+        (0x1000, _, _) => 3,
+        (0x2000, _, _) => 4,
+        (0x3000, _, _) => 5,
+        (0x4000, _, _) => 6,
+        (0x5000, 0x0, _) => 7,
+        (0x6000, _, _) => 8,
+        (0x7000, _, _) => 9,
+        (0x8000, n @ 0x0..=0x7, _) => 10 + n as usize,
+        (0x8000, 0xE, _) => 18,
+        (0x9000, 0x0, _) => 19,
+        (0xA000, _, _) => 20,
+        (0xB000, _, _) => 21,
+        (0xC000, _, _) => 22,
+        (0xD000, _, _) => 23,
+        (0xE000, _, 0x9E) => 24,
+        (0xE000, _, 0xA1) => 25,
+        (0xF000, _, 0x07) => 26,
+        (0xF000, _, 0x0A) => 27,
+        (0xF000, _, 0x15) => 28,
+        (0xF000, _, 0x18) => 29,
+        (0xF000, _, 0x1E) => 30,
+        (0xF000, _, 0x29) => 31,
+        (0xF000, _, 0x33) => 32,
+        (0xF000, _, 0x55) => 33,
+        (0xF000, _, 0x65) => 34,
+        _ => OTHER,
+    }
+}
+
+pub const DEFAULT_REWIND_DEPTH: usize = 64;
+
+// called by run_frame with the display, its width and its height after frames that changed it
+pub type FrameHook = Box<dyn FnMut(&[u8], usize, usize) + Send>;
+// called by step with the address, opcode and decoded instruction of every instruction just before it runs
+pub type TraceHook = Box<dyn FnMut(u16, u16, &Instruction) + Send>;
+// called with each overwrite of already executed code the self-modify checks find
+pub type OverwriteHook = Box<dyn FnMut(CodeOverwrite) + Send>;
+
+// a callback as the machine holds it: closures can't be cloned or compared, so a cloned machine
+// starts without any and they don't count when comparing machines
+struct Hook<T>(Option<T>);
+
+impl<T> Clone for Hook<T> {
+    fn clone(&self) -> Self {
+        Self(None)
+    }
+}
+
+impl<T> Default for Hook<T> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<T> PartialEq for Hook<T> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+// everything needed to put the machine back the way it was before a cycle
+#[derive(Clone, PartialEq)]
+struct RewindState {
+    memory: Vec<u8>,
+    vregister: [u8; 16],
+    index_register: u16,
+    program_counter: u16,
+    stack_pointer: u8,
+    stack: [u16; 16],
+    delay_timer: u8,
+    sound_timer: u8,
+    display: Vec<u8>,
+    hires: bool,
+    plane: u8,
+    audio_pattern: [u8; 16],
+    flag_registers: [u8; 8],
+    machine_cycles: u64,
+    instructions: u64,
+}
+
+// with the serde feature the whole machine can be serialized except the rng, the hooks and the
+// rewind history, which come back as they are on a new machine
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CHIP8 {
-    pub memory: [u8; 4096],
+    pub memory: Vec<u8>,
     pub vregister: [u8; 16],
     pub index_register: u16,
     pub program_counter: u16,
@@ -11,16 +329,78 @@ pub struct CHIP8 {
     pub delay_timer: u8,
     pub sound_timer: u8,
 
-    pub display: [u8; 64 * 32],
+    // each pixel holds one bit per XO-CHIP plane: bit 0 is plane 1, bit 1 is plane 2
+    // so plain chip8 pixels are 0 or 1 and XO-CHIP pixels can be 0-3
+    // it's display_width() * display_height() pixels, row by row, and resized by 00FE/00FF
+    pub display: Vec<u8>,
     pub keypad: [bool; 16],
 
+    // size of the rom loaded at 0x200, so it can be told apart from the empty memory after it
+    rom_len: usize,
+
+    // set once the program jumps to itself (the usual way chip8 programs end), cycle() does nothing after that
+    halted: bool,
+    // set whenever the display is changed (cleared or drawn to), run_frame reports and resets it
+    draw_flag: bool,
+    hires: bool, // SUPER-CHIP 128x64 mode, switched by 00FE/00FF
+
+    pub plane: u8, // XO-CHIP selected drawing plane(s), a bitmask set by Fx01
+    pub audio_pattern: [u8; 16], // XO-CHIP 1-bit audio pattern loaded by F002
+    // SUPER-CHIP flag registers saved by Fx75 and restored by Fx85, games keep high scores here
+    // (the HP48 kept them in its own memory so they survive a reset)
+    pub flag_registers: [u8; 8],
+
+    pub quirks: Quirks,
+    // instructions from later variants are errors, set_variant also picks the matching quirks
+    // (XO-CHIP, the default, runs everything this emulator knows)
+    pub variant: Variant,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    rng: MachineRng,
+
     pub debug: bool,
+    pub protect_interpreter_region: bool, // reject write_mem calls below 0x200
+    pub strict_machine_calls: bool, // 0nnn machine code calls are errors instead of being skipped
+    pub on_unknown_opcode: UnknownOpcodePolicy,
+    pub strict_memory: bool, // instructions writing below 0x200 (fonts, interpreter) are errors
+    timers_frozen: bool,     // see freeze_timers()
+
+    pub breakpoints: BTreeSet<u16>,
+
+    rewind_depth: usize, // 0 means rewinding is disabled
+    #[cfg_attr(feature = "serde", serde(skip))]
+    history: VecDeque<RewindState>,
+
+    profiling: bool,
+    #[cfg_attr(feature = "serde", serde(with = "serde_big_array::BigArray"))]
+    profile: [u64; OPCODE_CATEGORIES.len()], // executions per OPCODE_CATEGORIES entry
+
+    coverage: bool,
+    executed: Vec<bool>, // per memory byte, set for both bytes of every instruction run while coverage is on
+    self_modify_checks: bool,
+    tracking_unimplemented: bool,
+    unimplemented: BTreeSet<u16>, // distinct unknown or unsupported opcodes run while tracking_unimplemented is on
+    #[cfg_attr(feature = "serde", serde(skip))]
+    code_overwrites: Vec<CodeOverwrite>,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    frame_hook: Hook<FrameHook>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    trace_hook: Hook<TraceHook>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    overwrite_hook: Hook<OverwriteHook>,
+
+    machine_cycles: u64, // see machine_cycles()
+    instructions: u64,   // see instructions_executed()
+
+    // flicker reduction: lit pixels are 255, unlit ones lose `persistence` every frame
+    persistence: Option<u8>,
+    intensity: Vec<u8>,
 }
 
 impl CHIP8 {
     pub fn new() -> Self {
-        return Self {
-            memory: [0; 4096], // empty memory
+        Self {
+            memory: vec![0; MEMORY_SIZE], // empty memory
             vregister: [0; 16],
             index_register: 0x0,
             program_counter: 0x200,
@@ -30,395 +410,3551 @@ impl CHIP8 {
             delay_timer: 0,
             sound_timer: 0,
 
-            display: [0; 64 * 32], // black screen
-            keypad: [false; 16],   // the 16-key hexadecimal keypad
+            display: vec![0; CHIP8_WIDTH * CHIP8_HEIGHT], // black screen
+            keypad: [false; 16],                          // the 16-key hexadecimal keypad
+
+            rom_len: 0,
+
+            halted: false,
+            draw_flag: false,
+            hires: false,
+
+            plane: 1, // XO-CHIP draws to the first plane by default
+            audio_pattern: [0; 16],
+            flag_registers: [0; 8],
+
+            quirks: Quirks::default(),
+            variant: Variant::XoChip,
+            rng: MachineRng(rng::default_rng()),
 
             debug: false,
-        };
+            protect_interpreter_region: false,
+            strict_machine_calls: false,
+            on_unknown_opcode: UnknownOpcodePolicy::default(),
+            strict_memory: false,
+            timers_frozen: false,
+
+            breakpoints: BTreeSet::new(),
+
+            rewind_depth: 0,
+            history: VecDeque::new(),
+
+            profiling: false,
+            profile: [0; OPCODE_CATEGORIES.len()],
+
+            coverage: false,
+            executed: Vec::new(),
+            self_modify_checks: false,
+            tracking_unimplemented: false,
+            unimplemented: BTreeSet::new(),
+            code_overwrites: Vec::new(),
+
+            frame_hook: Hook(None),
+            trace_hook: Hook(None),
+            overwrite_hook: Hook(None),
+
+            machine_cycles: 0,
+            instructions: 0,
+
+            persistence: None,
+            intensity: vec![0; CHIP8_WIDTH * CHIP8_HEIGHT],
+        }
+    }
+
+    pub fn builder() -> CHIP8Builder {
+        CHIP8Builder::new()
+    }
+
+    // replace the random number source used by Cxkk
+    pub fn set_rng(&mut self, rng: Box<dyn Rng>) {
+        self.rng = MachineRng(rng);
+    }
+
+    // read a byte from memory, None if the address is outside of memory
+    pub fn read_mem(&self, addr: u16) -> Option<u8> {
+        self.memory.get(addr as usize).copied()
+    }
+
+    // write a byte to memory, checking bounds and the protected interpreter region
+    pub fn write_mem(&mut self, addr: u16, value: u8) -> Result<(), MemError> {
+        if self.protect_interpreter_region && addr < INTERPRETER_END {
+            return Err(MemError::Protected(addr));
+        }
+
+        match self.memory.get_mut(addr as usize) {
+            Some(byte) => {
+                *byte = value;
+                Ok(())
+            }
+            None => Err(MemError::OutOfBounds(addr)),
+        }
     }
 
     pub fn load_fonts(&mut self) {
-        const START_ADDRESS: usize = 0x50; // 80 decimal
-
-        // load fonts from 0x50 to 0x9F
-        const FONT_SET: [u8; 80] = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+        // load the big digits (0-9, 8x10 pixels) from 0xA0 to 0x103
+        const BIG_FONT_SET: [u8; 100] = [
+            0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+            0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+            0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+            0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+            0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+            0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+            0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+            0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+            0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
         ];
 
-        for (i, &byte) in FONT_SET.iter().enumerate() {
-            self.memory[START_ADDRESS + i] = byte;
+        self.set_font(&FONT_SET); // from 0x50 to 0x9F
+        self.set_big_font(&BIG_FONT_SET);
+    }
+
+    // replace the 5-byte hex digits used by Fx29 (0-F, 5 bytes each), e.g. to match another
+    // interpreter's glyphs exactly
+    pub fn set_font(&mut self, font: &[u8; 80]) {
+        self.memory[FONT_ADDRESS..FONT_ADDRESS + font.len()].copy_from_slice(font);
+    }
+
+    // replace the SUPER-CHIP 10-byte digits used by Fx30 (0-9, 10 bytes each)
+    pub fn set_big_font(&mut self, font: &[u8; 100]) {
+        self.memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + font.len()].copy_from_slice(font);
+    }
+
+    // load the rom file to the starting address (0x200)
+    #[cfg(feature = "std")]
+    pub fn load_rom(&mut self, rom_file: &str) -> Result<(), RomError> {
+        let data = fs::read(rom_file)?;
+        self.load_rom_bytes(&data)
+    }
+
+    // the one call a frontend needs: reset the machine, load the fonts and the rom file, then pick
+    // its quirks with apply_profile_for_loaded_rom. returns the variant the machine now runs as
+    // (the machine is left as it was if the file can't be read or doesn't fit in memory)
+    #[cfg(feature = "std")]
+    pub fn load_game(&mut self, path: &str) -> Result<Variant, RomError> {
+        let data = fs::read(path)?;
+        if data.len() > self.memory.len() - INTERPRETER_END as usize {
+            return Err(RomError::TooLarge(data.len()));
         }
+
+        self.reset();
+        self.load_fonts();
+        self.load_rom_bytes(&data)?;
+        self.apply_profile_for_loaded_rom();
+
+        Ok(self.variant)
     }
 
-    // load the rom to the starting address (0x200)
-    pub fn load_rom(&mut self, rom_file: &str) {
-        const START_ADDRESS: usize = 0x200; // 512 decimal
+    // load a rom that is already in memory to the starting address (0x200)
+    pub fn load_rom_bytes(&mut self, data: &[u8]) -> Result<(), RomError> {
+        self.load_at(data, INTERPRETER_END)?;
+        self.rom_len = data.len();
+
+        Ok(())
+    }
 
-        let data = fs::read(rom_file).unwrap();
+    // copy a blob to any address, useful for overlays or placing data in high memory
+    pub fn load_at(&mut self, data: &[u8], addr: u16) -> Result<(), RomError> {
+        let start = addr as usize;
+        if start >= self.memory.len() {
+            return Err(RomError::OutOfBounds(addr));
+        }
 
-        for (i, &byte) in data.iter().enumerate() {
-            self.memory[START_ADDRESS + i] = byte;
+        if data.len() > self.memory.len() - start {
+            return Err(RomError::TooLarge(data.len()));
         }
+
+        self.memory[start..start + data.len()].copy_from_slice(data);
+
+        Ok(())
     }
 
-    pub fn cycle(&mut self) {
-        let msb = self.memory[self.program_counter as usize];
-        let lsb = self.memory[(self.program_counter + 1) as usize];
+    // size in bytes of the rom loaded by load_rom/load_rom_bytes, 0 before one is loaded
+    pub fn rom_len(&self) -> usize {
+        self.rom_len
+    }
 
-        let opcode: u16 = ((msb as u16) << 8) | lsb as u16; // read the instruction and then increment PC
-        self.program_counter += 0x02; // increment the counter to the next address (opcodes on the chip8 are 2 bytes)
+    // the address just past the last rom byte (a usize, a rom filling 64KB ends at 0x10000)
+    pub fn rom_end_address(&self) -> usize {
+        INTERPRETER_END as usize + self.rom_len
+    }
 
-        /*if self.debug {
-            println!(
-                "address: 0x{:x}, opcode: {:x}",
-                self.program_counter, opcode
-            );
-        }*/
+    // the loaded rom as it is in memory now
+    pub(crate) fn rom(&self) -> &[u8] {
+        let end = self.rom_end_address().min(self.memory.len());
+        &self.memory[INTERPRETER_END as usize..end]
+    }
 
-        // process the opcode
-        match opcode {
-            0x00E0 => {
-                // clear the display
-                if self.debug {
-                    println!("0x{:x} clearing screen", opcode)
-                }
+    // 64-bit FNV-1a hash of the loaded rom, for telling games apart (e.g. to look up their quirks)
+    // it's computed from memory, so hash right after loading if the program modifies itself
+    pub fn rom_hash(&self) -> u64 {
+        self.rom().iter().fold(0xcbf29ce484222325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
 
-                self.display = [0; 64 * 32];
-            }
-            0x00EE => {
-                // return from a subroutine
-                if self.debug {
-                    println!("0x{:x} returning from subroutine", opcode);
-                }
+    // patch a byte of the loaded rom with an "address:value" cheat in hex, e.g. "2A4:FF"
+    pub fn apply_cheat(&mut self, cheat: &str) -> Result<(), CheatError> {
+        let (addr, value) = self.parse_cheat(cheat)?;
+        self.memory[addr as usize] = value;
+        Ok(())
+    }
 
-                self.stack_pointer -= 1;
-                self.program_counter = self.stack[self.stack_pointer as usize];
-            }
-            0x1000..=0x1FFF => {
-                // jump to location nnn
-                // (check if opcode starts with 1 and is within range)
-                if self.debug {
-                    println!("0x{:x} jumping to location", opcode);
-                }
+    // apply a list of cheats, nothing is patched unless all of them are valid
+    pub fn apply_cheats(&mut self, cheats: &[&str]) -> Result<(), CheatError> {
+        let patches = cheats
+            .iter()
+            .map(|cheat| self.parse_cheat(cheat))
+            .collect::<Result<Vec<_>, _>>()?;
 
-                self.program_counter = opcode & 0x0FFF; // bitwise AND to remove the first nibble
-            }
-            0x2000..=0x2FFF => {
-                // call subroutine at nnn
-                if self.debug {
-                    println!("0x{:x} calling subroutine", opcode);
-                }
+        for (addr, value) in patches {
+            self.memory[addr as usize] = value;
+        }
+        Ok(())
+    }
 
-                self.stack[self.stack_pointer as usize] = self.program_counter;
-                self.stack_pointer += 1;
-                self.program_counter = opcode & 0x0FFF;
-            }
-            0x3000..=0x3FFF => {
-                // skip next instruction if Vx == kk
-                let reg = ((opcode & 0x0F00) >> 8) as usize;
-                let kk = (opcode & 0x00FF) as u8;
+    fn parse_cheat(&self, cheat: &str) -> Result<(u16, u8), CheatError> {
+        let (addr, value) = cheat.trim().split_once(':').ok_or(CheatError::Malformed)?;
+        let addr = u16::from_str_radix(addr, 16).map_err(|_| CheatError::Malformed)?;
+        let value = u8::from_str_radix(value, 16).map_err(|_| CheatError::Malformed)?;
 
-                if self.debug {
-                    println!(
-                        "0x{:x} skipping next instruction if register V{} == {}",
-                        opcode, reg, kk
-                    );
-                }
+        let rom = INTERPRETER_END as usize..self.rom_end_address().min(self.memory.len());
+        if !rom.contains(&(addr as usize)) {
+            return Err(CheatError::OutsideRom(addr));
+        }
 
-                if self.vregister[reg] == kk {
-                    self.program_counter += 2; // skip next instruction
-                }
-            }
-            0x4000..=0x4FFF => {
-                // skip next instruction if Vx != kk
-                let reg = ((opcode & 0x0F00) >> 8) as usize;
-                let kk = (opcode & 0x00FF) as u8;
+        Ok((addr, value))
+    }
 
-                if self.debug {
-                    println!(
-                        "0x{:x} skipping next instruction if register V{} != {}",
-                        opcode, reg, kk
-                    );
-                }
+    // switch to the variant and quirks the loaded rom needs: the ones from the known roms table if
+    // it's in there, otherwise SUPER-CHIP if it looks like a SUPER-CHIP rom and the defaults if not
+    // returns the name of the rom when it was recognised
+    // an unknown rom's variant only gates instructions when it's detected as SUPER-CHIP, detection
+    // can't prove a rom never uses an instruction so every other rom can run all of them
+    pub fn apply_profile_for_loaded_rom(&mut self) -> Option<&'static str> {
+        self.variant = Variant::XoChip;
 
-                if self.vregister[reg] != kk {
-                    self.program_counter += 2;
-                }
-            }
-            0x5000..=0x5FFF => {
-                // skip next instruction if Vx == Vy
-                let reg_x = ((opcode & 0x0F00) >> 8) as usize;
-                let reg_y = ((opcode & 0x00F0) >> 4) as usize;
+        if let Some((name, variant, quirks)) = known_rom(self.rom_hash()) {
+            // like set_variant, but with the rom's own profile rather than the variant's quirks
+            self.variant = variant;
+            self.quirks = quirks;
+            return Some(name);
+        }
 
-                if self.debug {
-                    println!(
-                        "0x{:x} skipping next instruction if register V{} == V{}",
-                        opcode, reg_x, reg_y
-                    );
-                }
+        match detect_variant(self.rom()) {
+            Variant::Chip8 => self.quirks = Quirks::default(),
+            variant => self.set_variant(variant),
+        }
 
-                if self.vregister[reg_x] == self.vregister[reg_y] {
-                    self.program_counter += 2;
-                }
+        None
+    }
+
+    // only allow the instructions of `variant` and use its quirks
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+        self.quirks = variant.quirks();
+    }
+
+    // put the machine back to its power-on state, keeping settings like debug, breakpoints and rewind depth,
+    // the hooks and the SUPER-CHIP flag registers
+    // (memory is cleared too, so fonts and the rom have to be loaded again)
+    pub fn reset(&mut self) {
+        *self = Self {
+            memory: vec![0; self.memory.len()],
+            quirks: self.quirks,
+            variant: self.variant,
+            rng: self.rng.clone(),
+            flag_registers: self.flag_registers,
+            debug: self.debug,
+            protect_interpreter_region: self.protect_interpreter_region,
+            strict_machine_calls: self.strict_machine_calls,
+            on_unknown_opcode: self.on_unknown_opcode,
+            strict_memory: self.strict_memory,
+            timers_frozen: self.timers_frozen,
+            breakpoints: core::mem::take(&mut self.breakpoints),
+            rewind_depth: self.rewind_depth,
+            profiling: self.profiling,
+            persistence: self.persistence,
+            coverage: self.coverage,
+            self_modify_checks: self.self_modify_checks,
+            tracking_unimplemented: self.tracking_unimplemented,
+            frame_hook: Hook(self.frame_hook.0.take()),
+            trace_hook: Hook(self.trace_hook.0.take()),
+            overwrite_hook: Hook(self.overwrite_hook.0.take()),
+            executed: if self.coverage {
+                vec![false; self.memory.len()]
+            } else {
+                Vec::new()
+            },
+            ..Self::new()
+        };
+    }
+
+    // V0-VF by index, None for anything past VF
+    pub fn get_register(&self, idx: usize) -> Option<u8> {
+        self.vregister.get(idx).copied()
+    }
+
+    // set V0-VF by index, returns false (and does nothing) for anything past VF
+    pub fn set_register(&mut self, idx: usize, value: u8) -> bool {
+        match self.vregister.get_mut(idx) {
+            Some(reg) => {
+                *reg = value;
+                true
             }
-            0x6000..=0x6FFF => {
-                // put value kk into register Vx
-                let reg = ((opcode & 0x0F00) >> 8) as usize;
-                let kk = (opcode & 0x00FF) as u8;
+            None => false,
+        }
+    }
 
-                if self.debug {
-                    println!("0x{:x} setting register V{} to {}", opcode, reg, kk);
-                }
+    pub fn index_register(&self) -> u16 {
+        self.index_register
+    }
+
+    pub fn set_index_register(&mut self, value: u16) {
+        self.index_register = value;
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, value: u16) {
+        self.program_counter = value;
+    }
+
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    // the return addresses of the subroutines currently running, the outermost call first
+    // (each is the address just after its 2nnn)
+    pub fn call_stack(&self) -> &[u16] {
+        &self.stack[..self.stack_depth()]
+    }
+
+    // how many subroutine calls deep the program is
+    pub fn stack_depth(&self) -> usize {
+        (self.stack_pointer as usize).min(self.stack.len())
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
 
-                self.vregister[reg] = kk;
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    // hold down key 0x0-0xF, returns false (and does nothing) for anything else
+    pub fn press_key(&mut self, key: u8) -> bool {
+        self.set_key(key, true)
+    }
+
+    // let go of key 0x0-0xF, returns false (and does nothing) for anything else
+    pub fn release_key(&mut self, key: u8) -> bool {
+        self.set_key(key, false)
+    }
+
+    pub fn set_keypad(&mut self, state: [bool; 16]) {
+        self.keypad = state;
+    }
+
+    // Ex9E/ExA1 can ask about any register value, only 0x0-0xF are real keys and the rest are never pressed
+    fn is_key_down(&self, key: u8) -> bool {
+        self.keypad.get(key as usize).copied().unwrap_or(false)
+    }
+
+    fn set_key(&mut self, key: u8, pressed: bool) -> bool {
+        match self.keypad.get_mut(key as usize) {
+            Some(k) => {
+                *k = pressed;
+                true
             }
-            0x7000..=0x7FFF => {
-                // set Vx = Vx + kk
-                let reg = ((opcode & 0x0F00) >> 8) as usize;
-                let kk = (opcode & 0x00FF) as u8;
+            None => false,
+        }
+    }
 
-                if self.debug {
-                    println!("0x{:x} adding {} to register V{}", opcode, kk, reg);
-                }
-
-                self.vregister[reg] = self.vregister[reg].wrapping_add(kk);
-            }
-            0x8000..=0x8FFF => {
-                // 0x8 has multiple variants, handle all here based on the last nibble
-                let reg_x = ((opcode & 0x0F00) >> 8) as usize;
-                let reg_y = ((opcode & 0x00F0) >> 4) as usize;
-                let last_nibble = opcode & 0x000F;
-
-                match last_nibble {
-                    0 => self.vregister[reg_x] = self.vregister[reg_y], // set Vx = Vy
-                    1 => self.vregister[reg_x] = self.vregister[reg_x] | self.vregister[reg_y], // set Vx = Vx OR Vy
-                    2 => self.vregister[reg_x] = self.vregister[reg_x] & self.vregister[reg_y], // set Vx = Vx AND Vy
-                    3 => self.vregister[reg_x] = self.vregister[reg_x] ^ self.vregister[reg_y], // set Vx = Vx XOR Vy
-                    4 => {
-                        let (result, carry) =
-                            self.vregister[reg_x].overflowing_add(self.vregister[reg_y]);
-
-                        self.vregister[reg_x] = result;
-                        self.vregister[0xF] = if carry { 1 } else { 0 };
-                    } // set Vx = Vx + Vy, set VF = carry
-                    5 => {
-                        self.vregister[0xF] = if self.vregister[reg_x] > self.vregister[reg_y] {
-                            1
-                        } else {
-                            0
-                        };
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
 
-                        self.vregister[reg_x] =
-                            self.vregister[reg_x].wrapping_sub(self.vregister[reg_y]);
-                    } // set Vx = Vx - Vy, set VF = NOT borrow
-                    6 => {
-                        let lsb = self.vregister[reg_x] & 0x01; // least-significant bit
-                        self.vregister[0xF] = if lsb == 1 { 1 } else { 0 };
-
-                        self.vregister[reg_x] /= 2; // shift right
-                    } // set Vx = Vx SHR (shift right) 1
-                    7 => {
-                        self.vregister[0xF] = if self.vregister[reg_y] > self.vregister[reg_x] {
-                            1
-                        } else {
-                            0
-                        };
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
 
-                        self.vregister[reg_x] =
-                            self.vregister[reg_y].wrapping_sub(self.vregister[reg_x]);
-                    } // set Vx = Vy - Vx, set VF = NOT borrow
-                    0xE => {
-                        let msb = (self.vregister[reg_x] & 0x80) >> 7; // most-significant bit
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
 
-                        self.vregister[0xF] = if msb == 1 { 1 } else { 0 };
-                        self.vregister[reg_x] = self.vregister[reg_x].wrapping_mul(2);
-                    } // set Vx = Vx SHL (shift left) 1
-                    _ => println!("unknown 0x8xxx opcode variant: {}", last_nibble),
-                }
+    // run cycles until PC lands on a breakpoint, an error occurs, the program halts or max_cycles
+    // have been executed (at least one instruction is executed unless the machine has already
+    // halted, so calling it again continues past a breakpoint)
+    pub fn run_until_break(&mut self, max_cycles: usize) -> RunResult {
+        if self.halted {
+            return RunResult {
+                cycles: 0,
+                reason: StopReason::Halted,
+            };
+        }
+
+        for cycles in 1..=max_cycles {
+            if let Err(e) = self.cycle() {
+                return RunResult {
+                    cycles,
+                    reason: StopReason::Error(e),
+                };
             }
-            0x9000..=0x9FFF => {
-                let reg_x = ((opcode & 0x0F00) >> 8) as usize;
-                let reg_y = ((opcode & 0x00F0) >> 4) as usize;
 
-                if self.vregister[reg_x] != self.vregister[reg_y] {
-                    self.program_counter += 2;
-                }
-            } // skip next instruction if Vx != Vy
-            0xA000..=0xAFFF => {
-                let nnn = opcode & 0x0FFF;
+            if self.breakpoints.contains(&self.program_counter) {
+                return RunResult {
+                    cycles,
+                    reason: StopReason::Breakpoint(self.program_counter),
+                };
+            }
 
-                self.index_register = nnn;
-            } // set I = nnn
-            0xB000..=0xBFFF => {
-                let nnn = opcode & 0x0FFF;
+            if self.halted {
+                return RunResult {
+                    cycles,
+                    reason: StopReason::Halted,
+                };
+            }
+        }
 
-                self.program_counter = nnn + self.vregister[0x0] as u16;
-            } // jump to location nnn + V0
-            0xC000..=0xCFFF => {
-                let reg = ((opcode & 0x0F00) >> 8) as usize;
-                let kk = (opcode & 0x00FF) as u8;
-                let random_byte: u8 = rand::random();
+        RunResult {
+            cycles: max_cycles,
+            reason: StopReason::CycleLimit,
+        }
+    }
 
-                self.vregister[reg] = random_byte & kk;
-            } // set Vx = random byte AND kk
-            0xD000..=0xDFFF => {
-                // sprites are 8 bits wide and n-bytes tall (+1 on the y-axis)
-                let n = opcode & 0x000F;
-                let x = self.vregister[((opcode & 0x0F00) >> 8) as usize] as usize;
-                let y = self.vregister[((opcode & 0x00F0) >> 4) as usize] as usize;
-
-                self.vregister[0xF] = 0;
-
-                let mut reading_bytes = Vec::new();
-
-                // read n amount of bytes starting from the index (I) register
-                // and push them to the reading_bytes vector
-                for i in 0..n {
-                    reading_bytes.push(self.memory[(self.index_register + i) as usize]);
-                }
-
-                // now go through each bit in the bytes
-                for (row, byte) in reading_bytes.iter().enumerate() {
-                    for col in 0..8 {
-                        let bit = (byte >> (7 - col)) & 0x01; // extract the bits from each byte each iteration
-
-                        // if the bit is on
-                        // then figure out the index equivalent to (x, y) on the screen and XOR with 1
-                        if bit == 1 {
-                            // for wrapping, use modulus on the pixels
-                            let pixel_x = (x + col) % 64;
-                            let pixel_y = (y + row) % 32;
-                            let pixel_index = pixel_x + pixel_y * 64;
-
-                            // if the pixel already is displaying something (meaning something is there)
-                            if self.display[pixel_index] == 1 {
-                                self.vregister[0xF] = 1; // collision happens, set VF to 1
-                            }
+    // run up to n instructions and return the address and instruction of each one that ran,
+    // stopping early when the program halts or an instruction fails (the failing one isn't included)
+    pub fn trace_run(&mut self, n: usize) -> Vec<(u16, Instruction)> {
+        let mut trace = Vec::new();
 
-                            self.display[pixel_index] ^= 1; // XOR the pixel
-                        }
+        for _ in 0..n {
+            if self.halted {
+                break;
+            }
+
+            let pc = self.program_counter;
+            let Some(opcode) = self.peek_opcode() else {
+                break;
+            };
+
+            match self.step() {
+                Ok(outcome) => {
+                    trace.push((pc, decode(opcode)));
+                    if outcome == StepOutcome::Halted {
+                        break;
                     }
                 }
-            } // display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision
-            0xE000..=0xEFFF => {
-                let reg = ((opcode & 0x0F00) >> 8) as usize;
-                let last_nibbles = opcode & 0x00FF;
+                Err(_) => break,
+            }
+        }
 
-                match last_nibbles {
-                    0x9E => {
-                        if self.keypad[self.vregister[reg] as usize] == true {
-                            self.program_counter += 2;
-                        }
-                    } // skip next instruction if key with the value of Vx is pressed
-                    0xA1 => {
-                        if self.keypad[self.vregister[reg] as usize] == false {
-                            self.program_counter += 2;
-                        }
-                    } // skip next instruction if key with the value of Vx is not pressed
-                    _ => println!("unknown last two nibbles of 0xExxx"),
-                }
-            }
-            0xF000..=0xFFFF => {
-                let reg = ((opcode & 0x0F00) >> 8) as usize;
-                let last_nibbles = opcode & 0x00FF;
-
-                match last_nibbles {
-                    0x07 => {
-                        self.vregister[reg] = self.delay_timer;
-                    } // set Vx = delay timer value
-                    0x0A => {
-                        let mut key: Option<u8> = None;
-
-                        // attempt to find a held key
-                        for (i, &k) in self.keypad.iter().enumerate() {
-                            if k {
-                                key = Some(i as u8);
-                                self.vregister[reg] = key.unwrap();
-                                break; // break out early
-                            }
-                        }
+        trace
+    }
 
-                        if key.is_none() {
-                            self.program_counter -= 2; // redo this instruction if
-                        }
-                    } // halt the program and wait for a key press, store the value of the key in Vx
-                    0x15 => {
-                        self.delay_timer = self.vregister[reg];
-                    } // set delay timer = Vx
-                    0x18 => {
-                        self.sound_timer = self.vregister[reg];
-                    } // set the sound timer = Vx
-                    0x1E => {
-                        self.index_register = self.index_register + (self.vregister[reg] as u16);
-                    } // set I = I + Vx
-                    0x29 => {
-                        let font_start = 0x50; // where the fonts start in memory
-                        let font_size = 5; // 5 bytes wide
-
-                        // set index register to where the digit stored in Vx starts
-                        // (where the fonts start + which digit * fonts size to jump to the correct one)
-                        self.index_register =
-                            (font_start + (self.vregister[reg] as usize) * font_size) as u16;
-                    } // set I = location of sprite for digit Vx
-                    0x33 => {
-                        let value = self.vregister[reg];
-
-                        self.memory[self.index_register as usize] = value / 100;
-                        self.memory[(self.index_register + 1) as usize] = (value / 10) % 10;
-                        self.memory[(self.index_register + 2) as usize] = value % 10;
-                    } // store BCD representation of Vx in memory locations I, I+1 and I+2
-                    0x55 => {
-                        let range = reg as usize;
-
-                        // loop and include Vx register itself
-                        for i in 0..=range {
-                            self.memory[self.index_register as usize + (i as usize)] =
-                                self.vregister[i as usize];
-                        }
-                    } // store registers V0 through Vx in memory starting at location I
-                    0x65 => {
-                        let range = reg as usize;
-
-                        // loop and include Vx register itself
-                        for i in 0..=range {
-                            self.vregister[i as usize] =
-                                self.memory[(self.index_register as usize) + (i as usize)];
-                        }
-                    } // read registers V0 through Vx from memory starting at location I
-                    _ => println!("unknown last two nibbles of 0xFxxx"),
-                }
+    // run up to max_cycles instructions for at most until `deadline`, so a host loop can hand the
+    // machine a time slice. it returns early once the program halts or waits for a key, as running
+    // more cycles wouldn't do anything until something outside the machine changes
+    #[cfg(feature = "std")]
+    pub fn run_budget(&mut self, max_cycles: usize, deadline: Option<Instant>) -> RunResult {
+        // reading the clock costs about as much as an instruction, so it's only checked every so often
+        const DEADLINE_CHECK_INTERVAL: usize = 32;
+
+        let mut cycles = 0;
+        let reason = loop {
+            if self.halted {
+                break StopReason::Halted;
             }
-            _ => {
-                if self.debug {
-                    println!("opcode 0x{:x} not yet implemented", opcode)
-                }
+            if cycles == max_cycles {
+                break StopReason::CycleLimit;
+            }
+            if cycles % DEADLINE_CHECK_INTERVAL == 0
+                && deadline.is_some_and(|d| Instant::now() >= d)
+            {
+                break StopReason::Deadline;
+            }
+
+            cycles += 1;
+            match self.step() {
+                Ok(StepOutcome::WaitingForKey) => break StopReason::WaitingForKey,
+                Ok(_) => {}
+                Err(e) => break StopReason::Error(e),
             }
+        };
+
+        RunResult { cycles, reason }
+    }
+
+    // None if the display matches expected, otherwise the actual and expected frames and a map of
+    // the differing pixels drawn in ascii ('#' on, '.' off), for readable test failures
+    pub fn display_diff(&self, expected: &[u8]) -> Option<String> {
+        if expected.len() != self.display.len() {
+            return Some(format!(
+                "expected {} pixels, the display has {}",
+                expected.len(),
+                self.display.len()
+            ));
         }
 
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+        if self.display[..] == *expected {
+            return None;
         }
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
+
+        let mut diff = String::new();
+        for (y, (row, expected_row)) in self
+            .display
+            .chunks(self.display_width())
+            .zip(expected.chunks(self.display_width()))
+            .enumerate()
+        {
+            for (&pixel, &expected_pixel) in row.iter().zip(expected_row) {
+                diff.push(if pixel != expected_pixel { '#' } else { '.' });
+            }
+            let _ = writeln!(diff, " {}", y);
         }
 
-        //if self.debug {
-        println!("PC: {:04X}, Opcode: {:04X}", self.program_counter, opcode);
-        //}
+        Some(format!(
+            "actual:\n{}expected:\n{}differences (row numbers on the right):\n{}",
+            ascii_frame(&self.display, self.display_width(), '#', '.'),
+            ascii_frame(expected, self.display_width(), '#', '.'),
+            diff
+        ))
+    }
+
+    // the display as text, a line per row with '█' for lit pixels and ' ' for unlit ones
+    pub fn render_ascii(&self) -> String {
+        self.render_ascii_with('█', ' ')
+    }
+
+    // render_ascii with other characters, e.g. '#' and '.' where the block doesn't show up well
+    pub fn render_ascii_with(&self, on: char, off: char) -> String {
+        ascii_frame(&self.display, self.display_width(), on, off)
+    }
+
+    // multi-line dump of the registers, stack, timers and the first 512 bytes of program memory
+    // every line starts with a fixed label so the output is easy to grep and diff
+    pub fn dump_state(&self) -> String {
+        let mut out = String::new();
+
+        for (i, value) in self.vregister.iter().enumerate() {
+            let _ = writeln!(out, "V{:X}: {:02X}", i, value);
+        }
+
+        let _ = writeln!(out, "I: {:04X}", self.index_register);
+        let _ = writeln!(out, "PC: {:04X}", self.program_counter);
+        let _ = writeln!(out, "SP: {:02X}", self.stack_pointer);
+
+        let stack: Vec<String> = self.stack.iter().map(|a| format!("{:04X}", a)).collect();
+        let _ = writeln!(out, "STACK: {}", stack.join(" "));
+
+        let _ = writeln!(out, "DT: {:02X}", self.delay_timer);
+        let _ = writeln!(out, "ST: {:02X}", self.sound_timer);
+
+        let start = INTERPRETER_END as usize;
+        let end = (start + 512).min(self.memory.len());
+        for (row, bytes) in self.memory[start..end].chunks(16).enumerate() {
+            let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let _ = writeln!(out, "MEM {:04X}: {}", start + row * 16, bytes.join(" "));
+        }
+
+        out
+    }
+
+    // classic hex dump of len bytes from start, 16 per row with the address and an ascii gutter
+    // 0200: 00 E0 A2 2A 60 0C 61 08 D0 1F 70 09 A2 39 D0 1F  |...*`.a...p..9..|
+    pub fn hexdump(&self, start: u16, len: usize) -> String {
+        let start = (start as usize).min(self.memory.len());
+        let end = start.saturating_add(len).min(self.memory.len());
+
+        let mut out = String::new();
+        for (row, bytes) in self.memory[start..end].chunks(16).enumerate() {
+            let _ = write!(out, "{:04X}:", start + row * 16);
+
+            for byte in bytes {
+                let _ = write!(out, " {:02X}", byte);
+            }
+
+            // line the gutter up on a short last row
+            for _ in bytes.len()..16 {
+                out.push_str("   ");
+            }
+
+            let ascii: String = bytes
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            let _ = writeln!(out, "  |{}|", ascii);
+        }
+
+        out
+    }
+
+    // write the current display to a png, each chip8 pixel becomes a scale x scale block
+    #[cfg(feature = "std")]
+    pub fn snapshot_png(&self, scale: usize, path: &str) -> io::Result<()> {
+        let scale = scale.max(1);
+        let (width, height) = (self.display_width() * scale, self.display_height() * scale);
+
+        let mut pixels = vec![0; width * height];
+        scale_into_palette(
+            &self.display,
+            self.display_width(),
+            self.display_height(),
+            &mut pixels,
+            scale,
+            &DEFAULT_PALETTE,
+        );
+
+        // 0xAARRGGBB -> RGB
+        let data: Vec<u8> = pixels
+            .iter()
+            .flat_map(|color| {
+                let [_, r, g, b] = color.to_be_bytes();
+                [r, g, b]
+            })
+            .collect();
+
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    // keep a per-pixel intensity that run_frame fades by `step` per frame once a pixel goes off,
+    // so sprites that get erased and redrawn every other frame don't flicker
+    pub fn enable_persistence(&mut self, step: u8) {
+        self.persistence = Some(step);
+    }
+
+    pub fn disable_persistence(&mut self) {
+        self.persistence = None;
+        self.intensity.fill(0);
+    }
+
+    // 0-255 per pixel, the same layout as display (all 0 while persistence is off)
+    pub fn persisted_framebuffer(&self) -> &[u8] {
+        &self.intensity
+    }
+
+    // the display packed one bit per pixel: row by row, the leftmost pixel in the top bit, so 256
+    // bytes in low resolution and 1024 in high (any lit XO-CHIP plane counts as lit)
+    pub fn framebuffer_packed(&self) -> Vec<u8> {
+        self.display
+            .chunks(8)
+            .map(|pixels| {
+                pixels
+                    .iter()
+                    .fold(0, |byte, &pixel| (byte << 1) | (pixel != 0) as u8)
+            })
+            .collect()
+    }
+
+    // replace the display with a framebuffer_packed one, lit pixels end up on plane 1
+    // returns false and leaves the display alone if data isn't the size for the current resolution
+    pub fn set_framebuffer_packed(&mut self, data: &[u8]) -> bool {
+        if data.len() != self.display.len() / 8 {
+            return false;
+        }
+
+        for (pixels, &byte) in self.display.chunks_mut(8).zip(data) {
+            for (bit, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = (byte >> (7 - bit)) & 1;
+            }
+        }
+        self.draw_flag = true;
+
+        true
+    }
+
+    fn age_intensity(&mut self) {
+        let Some(step) = self.persistence else {
+            return;
+        };
+
+        for (intensity, &pixel) in self.intensity.iter_mut().zip(self.display.iter()) {
+            *intensity = if pixel != 0 {
+                255
+            } else {
+                intensity.saturating_sub(step)
+            };
+        }
+    }
+
+    // start recording which addresses get executed (what's recorded so far is kept if it's already on)
+    pub fn enable_coverage(&mut self) {
+        self.coverage = true;
+        self.executed.resize(self.memory.len(), false);
+    }
+
+    pub fn disable_coverage(&mut self) {
+        self.coverage = false;
+    }
+
+    // warn about instructions overwriting code that has already run, a sign of accidental (or
+    // intentional) self-modifying code. this turns coverage on, since that's how "already run" is known
+    // each one is logged, kept in code_overwrites and passed to the overwrite hook if there is one
+    pub fn enable_self_modify_checks(&mut self) {
+        self.enable_coverage();
+        self.self_modify_checks = true;
+    }
+
+    pub fn disable_self_modify_checks(&mut self) {
+        self.self_modify_checks = false;
+    }
+
+    // every overwrite found so far, oldest first
+    pub fn code_overwrites(&self) -> &[CodeOverwrite] {
+        &self.code_overwrites
+    }
+
+    // remember every distinct opcode this emulator doesn't know that the program runs, to see what
+    // a rom that doesn't work needs (they're recorded before on_unknown_opcode decides what happens)
+    // instructions the selected variant doesn't have count too, e.g. 00FF with Variant::Chip8
+    pub fn enable_unimplemented_tracking(&mut self) {
+        self.tracking_unimplemented = true;
+    }
+
+    pub fn disable_unimplemented_tracking(&mut self) {
+        self.tracking_unimplemented = false;
+    }
+
+    // the unknown and unsupported opcodes found so far, in ascending order
+    pub fn unimplemented_opcodes(&self) -> Vec<u16> {
+        self.unimplemented.iter().copied().collect()
+    }
+
+    // the rom split into address ranges that were and weren't executed while coverage was on
+    //   0200-0229 executed
+    //   022A-0263 not executed
+    // ranges that never ran are dead code or, more often, data
+    pub fn coverage_report(&self) -> String {
+        let start = INTERPRETER_END as usize;
+        let end = self.rom_end_address().min(self.memory.len());
+
+        let executed = |addr: usize| self.executed.get(addr).copied().unwrap_or(false);
+
+        let mut out = String::new();
+        let mut range_start = start;
+        for addr in start..end {
+            // close the current range when the next address is on the other side (or past the rom)
+            if addr + 1 == end || executed(addr) != executed(addr + 1) {
+                let label = if executed(addr) {
+                    "executed"
+                } else {
+                    "not executed"
+                };
+                let _ = writeln!(out, "{:04X}-{:04X} {}", range_start, addr, label);
+                range_start = addr + 1;
+            }
+        }
+
+        out
+    }
+
+    // start counting how often each kind of instruction runs (counts are kept if it's already on)
+    pub fn enable_profiling(&mut self) {
+        self.profiling = true;
+    }
+
+    pub fn disable_profiling(&mut self) {
+        self.profiling = false;
+    }
+
+    // execution counts per instruction category, indexed like OPCODE_CATEGORIES
+    pub fn profile(&self) -> &[u64] {
+        &self.profile
+    }
+
+    // one "category: count" line per instruction that ran, most executed first
+    pub fn profile_report(&self) -> String {
+        let mut counts: Vec<(&str, u64)> = OPCODE_CATEGORIES
+            .iter()
+            .copied()
+            .zip(self.profile.iter().copied())
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        counts.sort_by_key(|&(_, count)| core::cmp::Reverse(count));
+
+        let mut out = String::new();
+        for (category, count) in counts {
+            let _ = writeln!(out, "{}: {}", category, count);
+        }
+
+        out
+    }
+
+    // keep the last `depth` machine states so step_back can undo cycles (see DEFAULT_REWIND_DEPTH)
+    pub fn enable_rewind(&mut self, depth: usize) {
+        self.rewind_depth = depth;
+        while self.history.len() > depth {
+            self.history.pop_front();
+        }
+    }
+
+    // undo the most recent cycle
+    pub fn step_back(&mut self) -> Result<(), RewindError> {
+        if self.rewind_depth == 0 {
+            return Err(RewindError::Disabled);
+        }
+
+        let state = self.history.pop_back().ok_or(RewindError::Empty)?;
+
+        self.memory = state.memory;
+        self.vregister = state.vregister;
+        self.index_register = state.index_register;
+        self.program_counter = state.program_counter;
+        self.stack_pointer = state.stack_pointer;
+        self.stack = state.stack;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.display = state.display;
+        self.hires = state.hires;
+        self.plane = state.plane;
+        self.audio_pattern = state.audio_pattern;
+        self.flag_registers = state.flag_registers;
+        self.machine_cycles = state.machine_cycles;
+        self.instructions = state.instructions;
+        self.intensity.resize(self.display.len(), 0);
+        self.halted = false; // the state we went back to was still running
+
+        Ok(())
+    }
+
+    fn save_rewind_state(&mut self) {
+        if self.history.len() == self.rewind_depth {
+            self.history.pop_front(); // drop the oldest state
+        }
+
+        self.history.push_back(RewindState {
+            memory: self.memory.clone(),
+            vregister: self.vregister,
+            index_register: self.index_register,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            stack: self.stack,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            display: self.display.clone(),
+            hires: self.hires,
+            plane: self.plane,
+            audio_pattern: self.audio_pattern,
+            flag_registers: self.flag_registers,
+            machine_cycles: self.machine_cycles,
+            instructions: self.instructions,
+        });
+    }
+
+    // the nth register when walking from x to y inclusive, counting down when x > y (used by 5xy2 and 5xy3)
+    fn register_in_range(x: usize, y: usize, n: usize) -> usize {
+        if x <= y {
+            x + n
+        } else {
+            x - n
+        }
+    }
+
+    // the size of the display in pixels, what frontends should show display as
+    // (64x32, or 128x64 in SUPER-CHIP high resolution)
+    pub fn display_width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            CHIP8_WIDTH
+        }
+    }
+
+    pub fn display_height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            CHIP8_HEIGHT
+        }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // what 00FE/00FF do: the display is resized straight away, so the next DXYN already wraps and
+    // clips to the new size. with the resolution_switch_clears quirk it's blank afterwards,
+    // otherwise whatever fits keeps its coordinates
+    pub fn set_hires(&mut self, hires: bool) {
+        if hires == self.hires {
+            return;
+        }
+
+        let (old_width, old_height) = (self.display_width(), self.display_height());
+        self.hires = hires;
+        let (width, height) = (self.display_width(), self.display_height());
+
+        let mut display = vec![0; width * height];
+        if !self.quirks.resolution_switch_clears {
+            for y in 0..old_height.min(height) {
+                let common = old_width.min(width);
+                display[y * width..y * width + common]
+                    .copy_from_slice(&self.display[y * old_width..y * old_width + common]);
+            }
+        }
+        self.display = display;
+        self.intensity = vec![0; width * height];
+        self.draw_flag = true;
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // the opcode at PC, the one the next cycle will run
+    pub fn peek_opcode(&self) -> Option<u16> {
+        let pc = self.program_counter as usize;
+        let msb = *self.memory.get(pc)?;
+        let lsb = *self.memory.get(pc + 1)?;
+
+        Some(((msb as u16) << 8) | lsb as u16)
+    }
+
+    // count both timers down, this should be called at 60Hz independently of how many cycles run
+    pub fn tick_timers(&mut self) {
+        if self.timers_frozen {
+            return;
+        }
+
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    // hold both timers where they are, e.g. to step through a loop polling the delay timer
+    // instructions can still set them, only the 60Hz countdown stops
+    pub fn freeze_timers(&mut self, freeze: bool) {
+        self.timers_frozen = freeze;
+    }
+
+    pub fn timers_frozen(&self) -> bool {
+        self.timers_frozen
+    }
+
+    // one 60Hz frame: run ipf cycles then tick the timers once
+    // returns whether the display changed during the frame, so frontends can skip redrawing
+    pub fn run_frame(&mut self, ipf: usize) -> Result<bool, StepError> {
+        self.draw_flag = false;
+
+        for _ in 0..ipf {
+            if self.frame_step()? {
+                break;
+            }
+        }
+
+        Ok(self.end_frame())
+    }
+
+    // like run_frame, but runs instructions until they've taken `machine_cycles` (see cycle_cost)
+    // instead of a fixed number of them, e.g. VIP_CYCLES_PER_FRAME for the COSMAC VIP's speed
+    pub fn run_frame_cycles(&mut self, machine_cycles: u64) -> Result<bool, StepError> {
+        self.draw_flag = false;
+
+        let end = self.machine_cycles + machine_cycles;
+        while self.machine_cycles < end && !self.halted {
+            if self.frame_step()? {
+                break;
+            }
+        }
+
+        Ok(self.end_frame())
+    }
+
+    // run one instruction of a frame, returns true when the frame has to end early
+    fn frame_step(&mut self) -> Result<bool, StepError> {
+        // with the display wait quirk nothing else runs this frame once a sprite is drawn
+        let drawing = self.quirks.display_wait
+            && matches!(
+                self.peek_opcode().map(decode),
+                Some(Instruction::Draw { .. })
+            );
+
+        self.cycle()?;
+        Ok(drawing)
+    }
+
+    fn end_frame(&mut self) -> bool {
+        self.tick_timers();
+        self.age_intensity();
+
+        if self.draw_flag {
+            let (width, height) = (self.display_width(), self.display_height());
+            if let Some(hook) = &mut self.frame_hook.0 {
+                hook(&self.display, width, height);
+            }
+        }
+
+        self.draw_flag
+    }
+
+    // the total cycle_cost of every instruction run since the machine was created or reset
+    pub fn machine_cycles(&self) -> u64 {
+        self.machine_cycles
+    }
+
+    // how many instructions have run since the machine was created or reset
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn snapshot(&self) -> MachineSnapshot {
+        MachineSnapshot {
+            vregister: self.vregister,
+            index_register: self.index_register,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            stack: self.stack,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            instructions: self.instructions,
+            machine_cycles: self.machine_cycles,
+            halted: self.halted,
+            hires: self.hires,
+            waiting_for_key: matches!(
+                self.peek_opcode().map(decode),
+                Some(Instruction::WaitKey { .. })
+            ),
+        }
+    }
+
+    // run cycles until the display changes (a draw, clear or scroll), returns false if it didn't
+    // within max_cycles or the program halted or started waiting for a key first
+    // timers aren't ticked, this only batches up the cycles between redraws
+    pub fn run_until_draw(&mut self, max_cycles: usize) -> Result<bool, StepError> {
+        self.draw_flag = false;
+
+        for _ in 0..max_cycles {
+            match self.step()? {
+                _ if self.draw_flag => return Ok(true),
+                StepOutcome::Halted | StepOutcome::WaitingForKey => return Ok(false),
+                StepOutcome::Continue => {}
+            }
+        }
+
+        Ok(false)
+    }
+
+    // get every frame run_frame draws without implementing a whole Frontend
+    pub fn set_frame_hook(&mut self, hook: FrameHook) {
+        self.frame_hook = Hook(Some(hook));
+    }
+
+    pub fn clear_frame_hook(&mut self) {
+        self.frame_hook = Hook(None);
+    }
+
+    // see every instruction as it runs, e.g. to log a trace (this slows the interpreter down a lot)
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.trace_hook = Hook(Some(hook));
+    }
+
+    // the hook is dropped here, so anything it owns (like a trace file) is closed
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = Hook(None);
+    }
+
+    // hear about overwritten code as soon as it happens, see enable_self_modify_checks
+    pub fn set_overwrite_hook(&mut self, hook: OverwriteHook) {
+        self.overwrite_hook = Hook(Some(hook));
+    }
+
+    pub fn clear_overwrite_hook(&mut self) {
+        self.overwrite_hook = Hook(None);
+    }
+
+    // whether the buzzer should be sounding right now
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > self.quirks.sound_threshold
+    }
+
+    // the register 8xy6/8xyE shift, depending on the shift quirk
+    fn shift_source(&self, reg_x: usize, reg_y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.vregister[reg_y]
+        } else {
+            self.vregister[reg_x]
+        }
+    }
+
+    pub fn cycle(&mut self) -> Result<(), StepError> {
+        self.step().map(|_| ())
+    }
+
+    // fetch, decode and execute one instruction, like cycle() but also say what happened
+    pub fn step(&mut self) -> Result<StepOutcome, StepError> {
+        if self.halted {
+            return Ok(StepOutcome::Halted);
+        }
+
+        let pc = self.program_counter;
+        if pc as usize + 1 >= self.memory.len() {
+            return Err(StepError::PcOutOfBounds(pc));
+        }
+
+        // saved after the fetch check so a cycle that never ran doesn't leave a history entry
+        if self.rewind_depth > 0 {
+            self.save_rewind_state();
+        }
+
+        let msb = self.memory[pc as usize];
+        let lsb = self.memory[pc as usize + 1];
+
+        let opcode: u16 = ((msb as u16) << 8) | lsb as u16; // read the instruction and then increment PC
+        self.program_counter = self.program_counter.wrapping_add(2); // increment the counter to the next address (opcodes on the chip8 are 2 bytes)
+
+        if self.profiling {
+            self.profile[opcode_category(opcode)] += 1;
+        }
+        if self.coverage {
+            if let Some(bytes) = self.executed.get_mut(pc as usize..pc as usize + 2) {
+                bytes.fill(true);
+            }
+        }
+
+        let inst = decode(opcode);
+        if let Some(hook) = &mut self.trace_hook.0 {
+            hook(pc, opcode, &inst);
+        }
+        if self.tracking_unimplemented && !self.variant.supports(&inst) {
+            self.unimplemented.insert(opcode);
+        }
+
+        let outcome = self.execute(inst)?;
+        self.machine_cycles += cycle_cost(&inst) as u64;
+        self.instructions += 1;
+
+        if self.debug {
+            trace!("PC: {:04X}, Opcode: {:04X}", self.program_counter, opcode);
+        }
+
+        Ok(outcome)
+    }
+
+    // run one decoded instruction, PC has already been moved past it
+    pub fn execute(&mut self, inst: Instruction) -> Result<StepOutcome, StepError> {
+        use Instruction::*;
+
+        // the address of the instruction being executed, for errors and self-jumps
+        let pc = self.program_counter.wrapping_sub(2);
+
+        if !self.variant.supports(&inst) {
+            return Err(StepError::UnsupportedInVariant(pc));
+        }
+
+        match inst {
+            Sys(nnn) => {
+                // there's no RCA 1802 to run machine code on, the call is skipped like any other instruction
+                if self.strict_machine_calls {
+                    return Err(StepError::UnsupportedMachineCall(pc));
+                }
+
+                if self.debug {
+                    debug!("skipping machine code call to 0x{:x}", nnn)
+                }
+            }
+            Cls => {
+                // clear the display
+                if self.debug {
+                    debug!("clearing screen")
+                }
+
+                // only the selected planes are cleared (that's all of it for plain chip8)
+                for pixel in self.display.iter_mut() {
+                    *pixel &= !self.plane;
+                }
+                self.draw_flag = true;
+            }
+            Ret => {
+                // return from a subroutine
+                if self.debug {
+                    debug!("returning from subroutine");
+                }
+
+                if self.stack_pointer == 0 {
+                    return Err(StepError::StackUnderflow(pc));
+                }
+
+                self.stack_pointer -= 1;
+                self.program_counter = self.stack[self.stack_pointer as usize];
+            }
+            ScrollDown(n) => self.scroll(0, n as isize), // SUPER-CHIP: scroll down n pixels
+            ScrollUp(n) => self.scroll(0, -(n as isize)), // XO-CHIP: scroll up n pixels
+            ScrollRight => self.scroll(4, 0),            // SUPER-CHIP: scroll right 4 pixels
+            ScrollLeft => self.scroll(-4, 0),            // SUPER-CHIP: scroll left 4 pixels
+            Exit => {
+                self.halted = true;
+                return Ok(StepOutcome::Halted);
+            } // SUPER-CHIP: exit the interpreter
+            LowRes => self.set_hires(false),             // SUPER-CHIP: switch to 64x32
+            HighRes => self.set_hires(true),             // SUPER-CHIP: switch to 128x64
+            Jump(nnn) => {
+                // jump to location nnn
+                if self.debug {
+                    debug!("jumping to 0x{:x}", nnn);
+                }
+
+                self.program_counter = nnn;
+
+                // jumping to this same instruction loops forever, that's how programs end
+                if self.program_counter == pc {
+                    self.halted = true;
+                    return Ok(StepOutcome::Halted);
+                }
+            }
+            Call(nnn) => {
+                // call subroutine at nnn
+                if self.debug {
+                    debug!("calling subroutine at 0x{:x}", nnn);
+                }
+
+                if self.stack_pointer as usize >= self.stack.len() {
+                    return Err(StepError::StackOverflow(pc));
+                }
+
+                self.stack[self.stack_pointer as usize] = self.program_counter;
+                self.stack_pointer += 1;
+                self.program_counter = nnn;
+            }
+            SkipEqImm { x, kk } => {
+                // skip next instruction if Vx == kk
+                if self.debug {
+                    debug!("skipping next instruction if register V{} == {}", x, kk);
+                }
+
+                if self.vregister[x] == kk {
+                    self.program_counter = self.program_counter.wrapping_add(2);
+                    // skip next instruction
+                }
+            }
+            SkipNeImm { x, kk } => {
+                // skip next instruction if Vx != kk
+                if self.debug {
+                    debug!("skipping next instruction if register V{} != {}", x, kk);
+                }
+
+                if self.vregister[x] != kk {
+                    self.program_counter = self.program_counter.wrapping_add(2);
+                }
+            }
+            SkipEqReg { x, y } => {
+                // skip next instruction if Vx == Vy
+                if self.debug {
+                    debug!("skipping next instruction if register V{} == V{}", x, y);
+                }
+
+                if self.vregister[x] == self.vregister[y] {
+                    self.program_counter = self.program_counter.wrapping_add(2);
+                }
+            }
+            SaveRange { x, y } => {
+                // the whole range has to fit, so a failed save doesn't leave half of it written
+                let start = self.index_register as usize;
+                if start + x.abs_diff(y) >= self.memory.len() {
+                    return Err(StepError::MemoryOutOfBounds(self.index_register));
+                }
+                self.check_protected_write()?;
+
+                for offset in 0..=x.abs_diff(y) {
+                    let reg = Self::register_in_range(x, y, offset);
+                    self.memory[start + offset] = self.vregister[reg];
+                }
+            } // store registers Vx through Vy in memory starting at location I (I is unchanged)
+            LoadRange { x, y } => {
+                let start = self.index_register as usize;
+                if start + x.abs_diff(y) >= self.memory.len() {
+                    return Err(StepError::MemoryOutOfBounds(self.index_register));
+                }
+
+                for offset in 0..=x.abs_diff(y) {
+                    let reg = Self::register_in_range(x, y, offset);
+                    self.vregister[reg] = self.memory[start + offset];
+                }
+            } // read registers Vx through Vy from memory starting at location I (I is unchanged)
+            LoadImm { x, kk } => {
+                // put value kk into register Vx
+                if self.debug {
+                    debug!("setting register V{} to {}", x, kk);
+                }
+
+                self.vregister[x] = kk;
+            }
+            AddImm { x, kk } => {
+                // set Vx = Vx + kk, wrapping past 255
+                // unlike 8xy4 this never touches VF, there's no carry flag for immediate adds
+                if self.debug {
+                    debug!("adding {} to register V{}", kk, x);
+                }
+
+                self.vregister[x] = self.vregister[x].wrapping_add(kk);
+            }
+            Move { x, y } => self.vregister[x] = self.vregister[y], // set Vx = Vy
+            Or { x, y } => {
+                self.vregister[x] |= self.vregister[y];
+                self.logic_vf_reset();
+            } // set Vx = Vx OR Vy
+            And { x, y } => {
+                self.vregister[x] &= self.vregister[y];
+                self.logic_vf_reset();
+            } // set Vx = Vx AND Vy
+            Xor { x, y } => {
+                self.vregister[x] ^= self.vregister[y];
+                self.logic_vf_reset();
+            } // set Vx = Vx XOR Vy
+            Add { x, y } => {
+                let (result, carry) = self.vregister[x].overflowing_add(self.vregister[y]);
+
+                self.vregister[x] = result;
+                self.vregister[0xF] = if carry { 1 } else { 0 };
+            } // set Vx = Vx + Vy, set VF = carry
+            Sub { x, y } => {
+                // no borrow when Vx >= Vy (equal values give 0 without borrowing)
+                self.vregister[0xF] = if self.vregister[x] >= self.vregister[y] {
+                    1
+                } else {
+                    0
+                };
+
+                self.vregister[x] = self.vregister[x].wrapping_sub(self.vregister[y]);
+            } // set Vx = Vx - Vy, set VF = NOT borrow
+            ShiftRight { x, y } => {
+                let source = self.shift_source(x, y);
+                let lsb = source & 0x01; // least-significant bit
+                self.vregister[0xF] = if lsb == 1 { 1 } else { 0 };
+
+                self.vregister[x] = source / 2; // shift right
+            } // set Vx = Vx SHR (shift right) 1
+            SubN { x, y } => {
+                self.vregister[0xF] = if self.vregister[y] >= self.vregister[x] {
+                    1
+                } else {
+                    0
+                };
+
+                self.vregister[x] = self.vregister[y].wrapping_sub(self.vregister[x]);
+            } // set Vx = Vy - Vx, set VF = NOT borrow
+            ShiftLeft { x, y } => {
+                let source = self.shift_source(x, y);
+                let msb = (source & 0x80) >> 7; // most-significant bit
+
+                self.vregister[0xF] = if msb == 1 { 1 } else { 0 };
+                self.vregister[x] = source.wrapping_mul(2);
+            } // set Vx = Vx SHL (shift left) 1
+            SkipNeReg { x, y } => {
+                if self.vregister[x] != self.vregister[y] {
+                    self.program_counter = self.program_counter.wrapping_add(2);
+                }
+            } // skip next instruction if Vx != Vy
+            LoadI(nnn) => {
+                self.index_register = nnn;
+            } // set I = nnn
+            JumpOffset { x, nnn } => {
+                let reg = if self.quirks.jump_uses_vx { x } else { 0x0 };
+
+                // nnn + V0 can go past 0xFFF, rather than wrapping that's reported straight away
+                // using the same limit as the fetch would, instead of failing on the next cycle
+                let target = nnn + self.vregister[reg] as u16;
+                if target as usize + 1 >= self.memory.len() {
+                    return Err(StepError::OutOfBoundsJump(target));
+                }
+
+                self.program_counter = target;
+            } // jump to location nnn + V0 (or xnn + Vx with the jump quirk)
+            Random { x, kk } => {
+                let random_byte = self.rng.0.next_byte();
+
+                self.vregister[x] = random_byte & kk;
+            } // set Vx = random byte AND kk
+            Draw { x, y, n } => {
+                // sprites are 8 bits wide and n-bytes tall (+1 on the y-axis), n being a nibble caps
+                // them at 15 rows, except for the SUPER-CHIP Dxy0 form, a 16x16 sprite stored as two
+                // bytes per row
+                let (width, rows) = if n == 0 { (16, 16) } else { (8, n) };
+                let row_bytes = width / 8;
+                let size = rows * row_bytes;
+
+                // the starting position always wraps around the screen, as on every interpreter the
+                // presets cover, wrap_x/wrap_y only decide what happens to pixels past the edge
+                // (the current resolution's, 00FE/00FF resize the display as soon as they run)
+                let (screen_width, screen_height) = (self.display_width(), self.display_height());
+                let x = self.vregister[x] as usize % screen_width;
+                let y = self.vregister[y] as usize % screen_height;
+
+                // the sprite data for every selected plane has to be in memory before anything is drawn,
+                // so a bad I can't leave a half-drawn sprite behind
+                let planes = (self.plane & 0x3).count_ones() as usize;
+                let sprite_start = self.index_register as usize;
+                if sprite_start + size * planes > self.memory.len() {
+                    return Err(StepError::OutOfBoundsSpriteRead(self.index_register));
+                }
+
+                // collisions from every row of every plane are gathered here and VF is only written
+                // once at the end, so VF being Vx or Vy doesn't change where the sprite goes
+                let mut collision = false;
+                let mut sprite_address = sprite_start;
+
+                // each selected plane gets its own sprite data, one after the other
+                // (plain chip8 only ever uses the first plane)
+                for plane in [0x1, 0x2] {
+                    if self.plane & plane == 0 {
+                        continue;
+                    }
+
+                    // sprites are at most 32 bytes (16x16), so they fit in a fixed buffer
+                    let mut sprite = [0u8; 32];
+                    let reading_bytes = &mut sprite[..size];
+
+                    // read the sprite's bytes starting from the index (I) register
+                    // into the reading_bytes buffer
+                    reading_bytes
+                        .copy_from_slice(&self.memory[sprite_address..sprite_address + size]);
+                    sprite_address += size;
+
+                    // now go through each bit in the rows, left-aligned in a u16 so both widths work the same
+                    for (row, bytes) in reading_bytes.chunks(row_bytes).enumerate() {
+                        let line = match bytes {
+                            [left, right] => u16::from_be_bytes([*left, *right]),
+                            _ => (bytes[0] as u16) << 8,
+                        };
+
+                        for col in 0..width {
+                            let bit = (line >> (15 - col)) & 0x01; // extract the bits from each row each iteration
+
+                            // if the bit is on
+                            // then figure out the index equivalent to (x, y) on the screen and XOR the plane bit
+                            if bit == 1 {
+                                // parts of the sprite past an edge are either dropped or wrapped
+                                // around, each axis on its own
+                                let (pixel_x, pixel_y) = (x + col, y + row);
+                                if (!self.quirks.wrap_x && pixel_x >= screen_width)
+                                    || (!self.quirks.wrap_y && pixel_y >= screen_height)
+                                {
+                                    continue;
+                                }
+
+                                // for wrapping, use modulus on the pixels
+                                let pixel_x = pixel_x % screen_width;
+                                let pixel_y = pixel_y % screen_height;
+                                let pixel_index = pixel_x + pixel_y * screen_width;
+
+                                // if the pixel already is displaying something on this plane
+                                if self.display[pixel_index] & plane != 0 {
+                                    collision = true;
+                                }
+
+                                self.display[pixel_index] ^= plane; // XOR the pixel
+                            }
+                        }
+                    }
+                }
+
+                // VF = 1 if any selected plane had a collision
+                self.vregister[0xF] = collision as u8;
+                self.draw_flag = true;
+            } // display n-byte (or 16x16 for n = 0) sprite starting at memory location I at (Vx, Vy), set VF = collision
+            SkipKey { x } => {
+                if self.is_key_down(self.vregister[x]) {
+                    self.program_counter = self.program_counter.wrapping_add(2);
+                }
+            } // skip next instruction if key with the value of Vx is pressed
+            SkipNotKey { x } => {
+                if !self.is_key_down(self.vregister[x]) {
+                    self.program_counter = self.program_counter.wrapping_add(2);
+                }
+            } // skip next instruction if key with the value of Vx is not pressed
+            LoadLongI => {
+                let addr = self.program_counter as usize;
+                if addr + 1 >= self.memory.len() {
+                    return Err(StepError::MemoryOutOfBounds(addr as u16));
+                }
+
+                let value = ((self.memory[addr] as u16) << 8) | self.memory[addr + 1] as u16;
+                self.index_register = value & self.quirks.i_register_mask();
+                self.program_counter = self.program_counter.wrapping_add(2); // skip over the address word
+            } // XO-CHIP: set I = the 16-bit word following this instruction
+            SelectPlane(planes) => {
+                self.plane = planes;
+            } // XO-CHIP: select the drawing plane(s), a bitmask (0-3)
+            LoadAudio => {
+                let start = self.index_register as usize;
+                if start + self.audio_pattern.len() > self.memory.len() {
+                    return Err(StepError::MemoryOutOfBounds(self.index_register));
+                }
+
+                let end = start + self.audio_pattern.len();
+                self.audio_pattern.copy_from_slice(&self.memory[start..end]);
+            } // XO-CHIP: load the 16-byte audio pattern buffer from memory starting at I
+            LoadDelay { x } => {
+                self.vregister[x] = self.delay_timer;
+            } // set Vx = delay timer value
+            WaitKey { x } => {
+                // take the first held key, otherwise redo this instruction on the next cycle
+                match self.keypad.iter().position(|&k| k) {
+                    Some(key) => self.vregister[x] = key as u8,
+                    None => {
+                        self.program_counter = self.program_counter.wrapping_sub(2);
+                        return Ok(StepOutcome::WaitingForKey);
+                    }
+                }
+            } // halt the program and wait for a key press, store the value of the key in Vx
+            SetDelay { x } => {
+                self.delay_timer = self.vregister[x];
+            } // set delay timer = Vx
+            SetSound { x } => {
+                self.sound_timer = self.vregister[x];
+            } // set the sound timer = Vx
+            AddI { x } => {
+                let sum = self.index_register as u32 + self.vregister[x] as u32;
+                if self.quirks.fx1e_sets_vf {
+                    self.vregister[0xF] = (sum > 0x0FFF) as u8;
+                }
+                self.index_register = sum as u16 & self.quirks.i_register_mask();
+                // wraps at 4KB or 64KB
+            } // set I = I + Vx
+            LoadFont { x } => {
+                let font_start = FONT_ADDRESS; // where the fonts start in memory
+                let font_size = 5; // 5 bytes wide
+
+                // set index register to where the digit stored in Vx starts
+                // (where the fonts start + which digit * fonts size to jump to the correct one)
+                self.index_register =
+                    (font_start + (self.vregister[x] as usize) * font_size) as u16;
+            } // set I = location of sprite for digit Vx
+            LoadBigFont { x } => {
+                self.index_register = (BIG_FONT_ADDRESS + (self.vregister[x] as usize) * 10) as u16;
+            } // SUPER-CHIP: set I = location of the 10-byte sprite for digit Vx
+            Bcd { x } => {
+                let value = self.vregister[x];
+                let addr = self.index_register as usize;
+
+                // all three digits have to fit, don't write a partial result
+                if addr + 2 >= self.memory.len() {
+                    return Err(StepError::MemoryOutOfBounds(self.index_register));
+                }
+                self.check_protected_write()?;
+
+                self.memory[addr] = value / 100;
+                self.memory[addr + 1] = (value / 10) % 10;
+                self.memory[addr + 2] = value % 10;
+                self.check_code_overwrite(pc, addr, 3);
+            } // store BCD representation of Vx in memory locations I, I+1 and I+2
+            Store { x } => {
+                // V0 through Vx is x + 1 registers, all of them have to fit
+                let start = self.index_register as usize;
+                if start + x >= self.memory.len() {
+                    return Err(StepError::MemoryOutOfBounds(self.index_register));
+                }
+                self.check_protected_write()?;
+
+                // loop and include Vx register itself
+                for i in 0..=x {
+                    self.memory[start + i] = self.vregister[i];
+                }
+                self.check_code_overwrite(pc, start, x + 1);
+
+                if self.quirks.load_store_increments_i {
+                    self.index_register = self.index_register.wrapping_add(x as u16 + 1)
+                        & self.quirks.i_register_mask();
+                }
+            } // store registers V0 through Vx in memory starting at location I
+            Load { x } => {
+                let start = self.index_register as usize;
+                if start + x >= self.memory.len() {
+                    return Err(StepError::MemoryOutOfBounds(self.index_register));
+                }
+
+                // loop and include Vx register itself
+                for i in 0..=x {
+                    self.vregister[i] = self.memory[start + i];
+                }
+
+                if self.quirks.load_store_increments_i {
+                    self.index_register = self.index_register.wrapping_add(x as u16 + 1)
+                        & self.quirks.i_register_mask();
+                }
+            } // read registers V0 through Vx from memory starting at location I
+            SaveFlags { x } => {
+                // there are only 8 flag registers, so x is limited to 7
+                let count = x.min(7) + 1;
+                self.flag_registers[..count].copy_from_slice(&self.vregister[..count]);
+            } // SUPER-CHIP: store registers V0 through Vx in the flag registers
+            LoadFlags { x } => {
+                let count = x.min(7) + 1;
+                self.vregister[..count].copy_from_slice(&self.flag_registers[..count]);
+            } // SUPER-CHIP: read registers V0 through Vx from the flag registers
+            Unknown(opcode) => {
+                if self.tracking_unimplemented {
+                    self.unimplemented.insert(opcode);
+                }
+
+                match self.on_unknown_opcode {
+                    UnknownOpcodePolicy::Halt => {
+                        // PC stays on it, so it's what a debugger shows as the next instruction
+                        self.program_counter = pc;
+                        self.halted = true;
+                        return Ok(StepOutcome::Halted);
+                    }
+                    UnknownOpcodePolicy::Skip => warn!("unknown opcode 0x{:04X}", opcode),
+                    UnknownOpcodePolicy::Error => return Err(StepError::UnknownOpcode(pc)),
+                }
+            }
+        }
+
+        Ok(StepOutcome::Continue)
+    }
+
+    // move the selected planes of the display by dx, dy pixels, what scrolls in is blank
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.display_width(), self.display_height());
+        let previous = self.display.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x as isize - dx, y as isize - dy);
+                let inside =
+                    (0..width as isize).contains(&from_x) && (0..height as isize).contains(&from_y);
+                let scrolled = if inside {
+                    previous[from_y as usize * width + from_x as usize] & self.plane
+                } else {
+                    0
+                };
+
+                let pixel = &mut self.display[y * width + x];
+                *pixel = (*pixel & !self.plane) | scrolled;
+            }
+        }
+
+        self.draw_flag = true;
+    }
+
+    // with strict_memory, instructions that write upwards from I must not start below 0x200
+    // (as they only count up, I is the only address that can be inside the protected region)
+    fn check_protected_write(&self) -> Result<(), StepError> {
+        if self.strict_memory && self.index_register < INTERPRETER_END {
+            return Err(StepError::ProtectedWrite(self.index_register));
+        }
+
+        Ok(())
+    }
+
+    // with the self-modify checks on, note (and print) every byte of the write that had been executed
+    fn check_code_overwrite(&mut self, pc: u16, start: usize, len: usize) {
+        if !self.self_modify_checks {
+            return;
+        }
+
+        for addr in start..start + len {
+            if self.executed.get(addr).copied().unwrap_or(false) {
+                warn!("{:04X} overwrote already executed code at {:04X}", pc, addr);
+                let overwrite = CodeOverwrite {
+                    pc,
+                    addr: addr as u16,
+                };
+                if let Some(hook) = &mut self.overwrite_hook.0 {
+                    hook(overwrite);
+                }
+                self.code_overwrites.push(overwrite);
+            }
+        }
+    }
+
+    // the logic operations clear VF on the original COSMAC VIP
+    fn logic_vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.vregister[0xF] = 0;
+        }
+    }
+}
+
+impl Default for CHIP8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// one line of the machine's state for quick debug prints, e.g.
+//   PC=0x2A4 I=0x300 SP=2 DT=0 ST=0 cycles=1234
+// the alternate form ({:#}) adds the V registers: V=[00 1F ... 01]
+impl core::fmt::Display for CHIP8 {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "PC=0x{:03X} I=0x{:03X} SP={} DT={} ST={} cycles={}",
+            self.program_counter,
+            self.index_register,
+            self.stack_pointer,
+            self.delay_timer,
+            self.sound_timer,
+            self.instructions
+        )?;
+
+        if f.alternate() {
+            f.write_str(" V=[")?;
+            for (i, value) in self.vregister.iter().enumerate() {
+                let separator = if i == 0 { "" } else { " " };
+                write!(f, "{}{:02X}", separator, value)?;
+            }
+            f.write_str("]")?;
+        }
+
+        Ok(())
+    }
+}
+
+// chainable configuration for a new machine, e.g. CHIP8::builder().debug(true).build()
+pub struct CHIP8Builder {
+    quirks: Quirks,
+    variant: Variant,
+    rng: Box<dyn Rng>,
+    debug: bool,
+    memory_size: usize,
+
+    // starting state, handy for setting up exactly what a test needs
+    vregister: [u8; 16],
+    index_register: u16,
+    program_counter: u16,
+    pokes: Vec<(u16, u8)>,
+}
+
+impl CHIP8Builder {
+    pub fn new() -> Self {
+        Self {
+            quirks: Quirks::default(),
+            variant: Variant::XoChip,
+            rng: rng::default_rng(),
+            debug: false,
+            memory_size: MEMORY_SIZE,
+
+            vregister: [0; 16],
+            index_register: 0,
+            program_counter: INTERPRETER_END,
+            pokes: Vec::new(),
+        }
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    // also sets the variant's quirks, call quirks() afterwards to change them
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.variant = variant;
+        self.quirks = variant.quirks();
+        self
+    }
+
+    pub fn rng(mut self, rng: Box<dyn Rng>) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    // memory size in bytes, clamped between 0x200 (the interpreter area) and 64KB
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.memory_size = size.clamp(INTERPRETER_END as usize, MAX_MEMORY_SIZE);
+        self
+    }
+
+    // V0-VF
+    pub fn registers(mut self, vregister: [u8; 16]) -> Self {
+        self.vregister = vregister;
+        self
+    }
+
+    pub fn index_register(mut self, value: u16) -> Self {
+        self.index_register = value;
+        self
+    }
+
+    pub fn program_counter(mut self, value: u16) -> Self {
+        self.program_counter = value;
+        self
+    }
+
+    // write a byte to memory once it's created, pokes past the end of memory are dropped
+    pub fn poke(mut self, addr: u16, value: u8) -> Self {
+        self.pokes.push((addr, value));
+        self
+    }
+
+    // poke a whole opcode, big-endian like the machine reads it
+    pub fn poke_opcode(self, addr: u16, opcode: u16) -> Self {
+        let [msb, lsb] = opcode.to_be_bytes();
+        self.poke(addr, msb).poke(addr.wrapping_add(1), lsb)
+    }
+
+    pub fn build(self) -> CHIP8 {
+        let mut memory = vec![0; self.memory_size];
+        for (addr, value) in self.pokes {
+            if let Some(byte) = memory.get_mut(addr as usize) {
+                *byte = value;
+            }
+        }
+
+        CHIP8 {
+            memory,
+            vregister: self.vregister,
+            index_register: self.index_register,
+            program_counter: self.program_counter,
+            quirks: self.quirks,
+            variant: self.variant,
+            rng: MachineRng(self.rng),
+            debug: self.debug,
+            ..CHIP8::new()
+        }
+    }
+}
+
+// pixels as text, `width` to a line, any lit plane counts as on
+fn ascii_frame(pixels: &[u8], width: usize, on: char, off: char) -> String {
+    let mut out = String::new();
+    for row in pixels.chunks(width) {
+        out.extend(row.iter().map(|&pixel| if pixel != 0 { on } else { off }));
+        out.push('\n');
+    }
+
+    out
+}
+
+// fuzzing entry point: run arbitrary bytes as a rom for up to `cycles` instructions
+// no input can make this panic, bad programs end with the StepError they ran into
+// (data too big to be a rom is rejected by the loader and never runs)
+pub fn run_fuzz(rom: &[u8], cycles: usize) -> Result<(), StepError> {
+    let mut chip8 = CHIP8Builder::new()
+        .rng(Box::new(rng::SeededRng::new(0))) // so a crash found once can be reproduced
+        .build();
+    chip8.load_fonts();
+    if chip8.load_rom_bytes(rom).is_err() {
+        return Ok(());
+    }
+
+    for cycle in 0..cycles {
+        if chip8.is_halted() {
+            break;
+        }
+        chip8.cycle()?;
+
+        // the timers too, so delay loops and sound don't stay untested
+        if cycle % 10 == 9 {
+            chip8.tick_timers();
+        }
+    }
+
+    Ok(())
+}
+
+// run two machines with the same rom loaded in lockstep, e.g. with different quirks, and report
+// the first instruction after which their state differs (None if they agree for max_cycles or
+// both stop the same way)
+pub fn diff_run(a: &mut CHIP8, b: &mut CHIP8, max_cycles: usize) -> Option<DivergenceReport> {
+    for cycle in 1..=max_cycles {
+        let pc = a.program_counter;
+        let outcome = (a.step(), b.step());
+
+        let difference = if outcome.0 != outcome.1 {
+            Some(Difference::Outcome)
+        } else {
+            first_difference(a, b)
+        };
+        if let Some(difference) = difference {
+            return Some(DivergenceReport {
+                cycle,
+                pc,
+                difference,
+            });
+        }
+
+        // the same error or end for both, neither will do anything else
+        if !matches!(outcome.0, Ok(StepOutcome::Continue)) {
+            return None;
+        }
+    }
+
+    None
+}
+
+fn first_difference(a: &CHIP8, b: &CHIP8) -> Option<Difference> {
+    let first = |x: &[u8], y: &[u8]| x.iter().zip(y).position(|(x, y)| x != y);
+
+    if a.program_counter != b.program_counter {
+        Some(Difference::ProgramCounter)
+    } else if let Some(register) = first(&a.vregister, &b.vregister) {
+        Some(Difference::Register(register))
+    } else if a.index_register != b.index_register {
+        Some(Difference::IndexRegister)
+    } else if a.stack_pointer != b.stack_pointer || a.stack != b.stack {
+        Some(Difference::Stack)
+    } else if (a.delay_timer, a.sound_timer) != (b.delay_timer, b.sound_timer) {
+        Some(Difference::Timers)
+    } else if let Some(addr) = first(&a.memory, &b.memory) {
+        Some(Difference::Memory(addr as u16))
+    } else if a.hires != b.hires {
+        Some(Difference::Resolution)
+    } else {
+        first(&a.display, &b.display).map(Difference::Display)
+    }
+}
+
+impl Default for CHIP8Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_until_break_stops_at_breakpoint() {
+        let mut chip8 = CHIP8::new();
+        // 0x200: LD V0, 5 / JP 0x208, 0x208: JP 0x20A, 0x20A: JP 0x20A
+        chip8
+            .load_rom_bytes(&[0x60, 0x05, 0x12, 0x08, 0, 0, 0, 0, 0x12, 0x0A, 0x12, 0x0A])
+            .unwrap();
+        chip8.add_breakpoint(0x208);
+
+        let result = chip8.run_until_break(100);
+        assert_eq!(result.reason, StopReason::Breakpoint(0x208));
+        assert_eq!(result.cycles, 2);
+        assert_eq!(chip8.program_counter, 0x208);
+    }
+
+    #[test]
+    fn run_until_break_stops_when_halted() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x12, 0x00]).unwrap(); // JP 0x200
+
+        let result = chip8.run_until_break(100);
+        assert_eq!(result.reason, StopReason::Halted);
+        assert_eq!(result.cycles, 1);
+
+        let result = chip8.run_until_break(100);
+        assert_eq!(result.reason, StopReason::Halted);
+        assert_eq!(result.cycles, 0);
+    }
+
+    #[test]
+    fn step_back_returns_to_earlier_state() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01].repeat(10)).unwrap(); // ADD V0, 1 ten times
+        chip8.enable_rewind(DEFAULT_REWIND_DEPTH);
+
+        let mut pcs = Vec::new();
+        for _ in 0..10 {
+            pcs.push(chip8.program_counter);
+            chip8.cycle().unwrap();
+        }
+        for _ in 0..3 {
+            chip8.step_back().unwrap();
+        }
+
+        assert_eq!(chip8.program_counter, pcs[7]);
+        assert_eq!(chip8.vregister[0], 7);
+    }
+
+    #[test]
+    fn failed_fetch_leaves_no_history() {
+        let mut chip8 = CHIP8::new();
+        chip8.enable_rewind(DEFAULT_REWIND_DEPTH);
+        chip8.program_counter = (chip8.memory.len() - 1) as u16;
+
+        assert!(chip8.cycle().is_err());
+        assert_eq!(chip8.step_back(), Err(RewindError::Empty));
+    }
+
+    #[test]
+    fn step_back_restores_plane_and_audio_pattern() {
+        let mut chip8 = CHIP8::new();
+        // LD I, 0x300 / F002 (audio pattern from I) / F201 (plane 2)
+        chip8
+            .load_rom_bytes(&[0xA3, 0x00, 0xF0, 0x02, 0xF2, 0x01])
+            .unwrap();
+        chip8.memory[0x300..0x310].fill(0xAA);
+        chip8.enable_rewind(DEFAULT_REWIND_DEPTH);
+
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!((chip8.plane, chip8.audio_pattern), (2, [0xAA; 16]));
+
+        chip8.step_back().unwrap();
+        assert_eq!(chip8.plane, 1);
+        chip8.step_back().unwrap();
+        assert_eq!(chip8.audio_pattern, [0; 16]);
+    }
+
+    #[test]
+    fn long_load_reads_the_next_word() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0xF0, 0x00, 0x12, 0x34]).unwrap();
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.index_register, 0x1234);
+        assert_eq!(chip8.program_counter, 0x204);
+    }
+
+    #[test]
+    fn save_and_load_range() {
+        let mut chip8 = CHIP8::new();
+        // 5252 (save V2..V5) / 5553 (load V5..V2), I = 0x300
+        chip8.load_rom_bytes(&[0x52, 0x52, 0x55, 0x23]).unwrap();
+        chip8.vregister[2..6].copy_from_slice(&[1, 2, 3, 4]);
+        chip8.index_register = 0x300;
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.memory[0x300..0x304], [1, 2, 3, 4]);
+        assert_eq!(chip8.index_register, 0x300);
+
+        chip8.vregister = [0; 16];
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.vregister[2..6], [4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn save_range_past_the_end_writes_nothing() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x50, 0x32]).unwrap(); // save V0..V3
+        chip8.vregister[..4].copy_from_slice(&[1, 2, 3, 4]);
+        chip8.index_register = (chip8.memory.len() - 2) as u16;
+
+        assert_eq!(
+            chip8.cycle(),
+            Err(StepError::MemoryOutOfBounds(chip8.index_register))
+        );
+        assert_eq!(chip8.memory[chip8.memory.len() - 2..], [0, 0]);
+    }
+
+    #[test]
+    fn flag_registers_survive_clearing_the_v_registers() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0xF3, 0x75, 0xF3, 0x85]).unwrap(); // Fx75 then Fx85 for V0..V3
+        chip8.vregister[..4].copy_from_slice(&[1, 2, 3, 4]);
+
+        chip8.cycle().unwrap();
+        chip8.vregister = [0; 16];
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.vregister[..5], [1, 2, 3, 4, 0]);
+    }
+
+    #[test]
+    fn step_back_restores_flag_registers() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x60, 0x05, 0xF0, 0x75]).unwrap(); // LD V0, 5 / Fx75
+        chip8.enable_rewind(DEFAULT_REWIND_DEPTH);
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.flag_registers[0], 5);
+
+        chip8.step_back().unwrap();
+        assert_eq!(chip8.flag_registers[0], 0);
+    }
+
+    #[test]
+    fn run_frame_cycles_uses_the_cycle_costs() {
+        let mut chip8 = CHIP8::new();
+        chip8
+            .load_rom_bytes(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x02])
+            .unwrap();
+        chip8
+            .run_frame_cycles(crate::instruction::VIP_CYCLES_PER_FRAME)
+            .unwrap();
+
+        assert!(chip8.machine_cycles() >= crate::instruction::VIP_CYCLES_PER_FRAME);
+    }
+
+    #[test]
+    fn step_back_restores_machine_cycles() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x60, 0x01, 0x70, 0x01]).unwrap();
+        chip8.enable_rewind(DEFAULT_REWIND_DEPTH);
+
+        chip8.cycle().unwrap();
+        let after_one = chip8.machine_cycles();
+        chip8.cycle().unwrap();
+        chip8.step_back().unwrap();
+
+        assert_eq!(chip8.machine_cycles(), after_one);
+    }
+
+    #[test]
+    fn snapshot_reflects_registers_and_pc() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x67, 0x42, 0x12, 0x02]).unwrap(); // LD V7, 0x42 / JP 0x202
+        chip8.cycle().unwrap();
+
+        let snapshot = chip8.snapshot();
+        assert_eq!(snapshot.vregister[7], 0x42);
+        assert_eq!(snapshot.program_counter, 0x202);
+        assert_eq!(snapshot.instructions, 1);
+    }
+
+    #[test]
+    fn step_back_restores_the_whole_snapshot() {
+        let mut chip8 = CHIP8::new();
+        // F201 (plane 2) / LD V0, 5 / F075 (save V0 to the flags)
+        chip8
+            .load_rom_bytes(&[0xF2, 0x01, 0x60, 0x05, 0xF0, 0x75])
+            .unwrap();
+        chip8.enable_rewind(DEFAULT_REWIND_DEPTH);
+        let before = chip8.clone();
+
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        for _ in 0..3 {
+            chip8.step_back().unwrap();
+        }
+
+        assert_eq!(chip8.snapshot(), before.snapshot());
+        assert_eq!(chip8.plane, 1);
+        assert_eq!(chip8.flag_registers[0], 0);
+        assert_eq!(chip8.instructions_executed(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn load_game_applies_the_detected_variant() {
+        let path = std::env::temp_dir().join(format!("chip8-load-game-{}.ch8", std::process::id()));
+        std::fs::write(&path, [0x00, 0xFB, 0x12, 0x02]).unwrap(); // a SUPER-CHIP scroll
+        let mut chip8 = CHIP8::new();
+
+        let variant = chip8.load_game(path.to_str().unwrap()).unwrap();
+        assert_eq!(variant, Variant::SuperChip);
+        assert_eq!(chip8.variant, Variant::SuperChip);
+        assert_eq!(chip8.quirks, Quirks::super_chip());
+
+        // a plain chip8 rom runs with every instruction available
+        std::fs::write(&path, [0x00, 0xE0, 0x12, 0x02]).unwrap();
+        assert_eq!(
+            chip8.load_game(path.to_str().unwrap()).unwrap(),
+            Variant::XoChip
+        );
+        assert_eq!(chip8.quirks, Quirks::default());
+        let _ = std::fs::remove_file(path);
+
+        // known roms get the variant from the table
+        assert_eq!(
+            chip8.load_game("roms/c8games/BLINKY").unwrap(),
+            Variant::SuperChip
+        );
+        assert_eq!(chip8.quirks, Quirks::super_chip());
+        assert_eq!(
+            chip8.load_game("roms/c8games/BLITZ").unwrap(),
+            Variant::Chip8
+        );
+        assert!(chip8.quirks.display_wait);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn load_game_keeps_the_machine_when_the_rom_is_too_large() {
+        let path = std::env::temp_dir().join(format!(
+            "chip8-load-game-too-large-{}.ch8",
+            std::process::id()
+        ));
+        std::fs::write(&path, vec![0; MEMORY_SIZE]).unwrap();
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x60, 0x05]).unwrap();
+        chip8.cycle().unwrap();
+        let before = chip8.clone();
+
+        assert!(matches!(
+            chip8.load_game(path.to_str().unwrap()),
+            Err(RomError::TooLarge(_))
+        ));
+        assert!(chip8 == before);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn sprites_wrap_or_clip_at_a_corner() {
+        for (wrap_x, wrap_y) in [(true, false), (true, true), (false, true), (false, false)] {
+            let mut chip8 = CHIP8::new();
+            chip8.quirks.wrap_x = wrap_x;
+            chip8.quirks.wrap_y = wrap_y;
+            // an 8x2 block at (60, 31), so it crosses the right and the bottom edge
+            chip8.load_rom_bytes(&[0xD0, 0x12]).unwrap();
+            chip8.memory[0x300..0x302].fill(0xFF);
+            chip8.index_register = 0x300;
+            chip8.vregister[0] = 60;
+            chip8.vregister[1] = 31;
+            chip8.cycle().unwrap();
+
+            assert_eq!(chip8.display[31 * 64 + 63], 1);
+            assert_eq!(chip8.display[31 * 64], wrap_x as u8);
+            assert_eq!(chip8.display[63], wrap_y as u8);
+            assert_eq!(chip8.display[0], (wrap_x && wrap_y) as u8);
+        }
+    }
+
+    #[test]
+    fn sprite_origin_past_the_edge_always_wraps() {
+        for quirks in [
+            Quirks::default(),
+            Quirks::cosmac_vip(),
+            Quirks::super_chip(),
+        ] {
+            let mut chip8 = CHIP8::builder().quirks(quirks).build();
+            // a single pixel at (64 + 2, 32 + 3)
+            chip8.load_rom_bytes(&[0xD0, 0x11]).unwrap();
+            chip8.memory[0x300] = 0x80;
+            chip8.index_register = 0x300;
+            chip8.vregister[0] = 66;
+            chip8.vregister[1] = 35;
+            chip8.cycle().unwrap();
+
+            assert_eq!(chip8.display.iter().filter(|&&p| p != 0).count(), 1);
+            assert_eq!(chip8.display[3 * CHIP8_WIDTH + 2], 1);
+        }
+    }
+
+    #[test]
+    fn dump_state_lists_the_registers() {
+        let mut chip8 = CHIP8::new();
+        chip8.vregister[5] = 0xAB;
+
+        let dump = chip8.dump_state();
+        for label in [
+            "V0: 00", "V5: AB", "I: 0000", "PC: 0200", "SP: 00", "DT: 00", "ST: 00",
+        ] {
+            assert!(dump.lines().any(|line| line == label), "{} missing", label);
+        }
+        assert!(dump.contains("MEM 0200: "));
+    }
+
+    #[test]
+    fn unknown_opcode_policies() {
+        for policy in [
+            UnknownOpcodePolicy::Halt,
+            UnknownOpcodePolicy::Skip,
+            UnknownOpcodePolicy::Error,
+        ] {
+            let mut chip8 = CHIP8::new();
+            chip8.load_rom_bytes(&[0x50, 0x1F, 0x60, 0x01]).unwrap(); // 501F isn't an instruction
+            chip8.on_unknown_opcode = policy;
+
+            let result = chip8.step();
+            match policy {
+                UnknownOpcodePolicy::Halt => {
+                    assert_eq!(result, Ok(StepOutcome::Halted));
+                    assert_eq!(chip8.program_counter, 0x200);
+                    assert!(chip8.is_halted());
+                }
+                UnknownOpcodePolicy::Skip => {
+                    assert_eq!(result, Ok(StepOutcome::Continue));
+                    assert_eq!(chip8.program_counter, 0x202);
+                }
+                UnknownOpcodePolicy::Error => {
+                    assert_eq!(result, Err(StepError::UnknownOpcode(0x200)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn subtraction_borrow_flags() {
+        // (V0, V1, V0 - V1 and its VF, V1 - V0 and its VF)
+        for (a, b, sub, sub_flag, subn, subn_flag) in [
+            (5, 5, 0, 1, 0, 1),
+            (7, 5, 2, 1, 0xFE, 0),
+            (5, 7, 0xFE, 0, 2, 1),
+        ] {
+            for (opcode, result, flag) in [(0x8015, sub, sub_flag), (0x8017, subn, subn_flag)] {
+                let mut chip8 = CHIP8::new();
+                chip8.load_rom_bytes(&u16::to_be_bytes(opcode)).unwrap();
+                chip8.vregister[0] = a;
+                chip8.vregister[1] = b;
+                chip8.cycle().unwrap();
+
+                assert_eq!(
+                    (chip8.vregister[0], chip8.vregister[0xF]),
+                    (result, flag),
+                    "{:04X} with V0 = {}, V1 = {}",
+                    opcode,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn add_immediate_wraps_without_touching_vf() {
+        let mut chip8 = CHIP8::new();
+        // LD V0, 0xFF / LD VF, 7 / ADD V0, 2
+        chip8
+            .load_rom_bytes(&[0x60, 0xFF, 0x6F, 0x07, 0x70, 0x02])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+
+        assert_eq!(chip8.vregister[0], 0x01);
+        assert_eq!(chip8.vregister[0xF], 7);
+    }
+
+    #[test]
+    fn add_registers_sets_the_carry() {
+        let mut chip8 = CHIP8::new();
+        // LD V0, 0xFF / LD V1, 2 / ADD V0, V1
+        chip8
+            .load_rom_bytes(&[0x60, 0xFF, 0x61, 0x02, 0x80, 0x14])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+
+        assert_eq!(chip8.vregister[0], 0x01);
+        assert_eq!(chip8.vregister[0xF], 1);
+    }
+
+    #[test]
+    fn memory_accessors_check_bounds() {
+        let mut chip8 = CHIP8::new();
+
+        assert_eq!(chip8.write_mem(0x300, 0x42), Ok(()));
+        assert_eq!(chip8.read_mem(0x300), Some(0x42));
+        assert_eq!(chip8.write_mem(0xFFF, 1), Ok(()));
+        assert_eq!(chip8.read_mem(0xFFF), Some(1));
+
+        assert_eq!(chip8.read_mem(0x1000), None);
+        assert_eq!(
+            chip8.write_mem(0x1000, 1),
+            Err(MemError::OutOfBounds(0x1000))
+        );
+
+        chip8.protect_interpreter_region = true;
+        assert_eq!(chip8.write_mem(0x100, 1), Err(MemError::Protected(0x100)));
+        assert_eq!(chip8.read_mem(0x100), Some(0));
+        assert_eq!(chip8.write_mem(0x200, 1), Ok(()));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn snapshot_png_writes_a_file() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_fonts();
+        // draw the 0 glyph in the top left corner
+        chip8.load_rom_bytes(&[0xA0, 0x50, 0xD0, 0x05]).unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+
+        let path = std::env::temp_dir().join("chip8_snapshot_test.png");
+        chip8.snapshot_png(4, path.to_str().unwrap()).unwrap();
+
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn register_accessors() {
+        let mut chip8 = CHIP8::new();
+
+        assert!(chip8.set_register(3, 0x42));
+        assert_eq!(chip8.get_register(3), Some(0x42));
+        assert!(chip8.set_register(0xF, 1));
+        assert_eq!(chip8.get_register(0xF), Some(1));
+        assert!(!chip8.set_register(16, 1));
+        assert_eq!(chip8.get_register(16), None);
+
+        chip8.set_index_register(0x345);
+        chip8.set_program_counter(0x220);
+        chip8.set_delay_timer(10);
+        chip8.set_sound_timer(20);
+        assert_eq!(chip8.index_register(), 0x345);
+        assert_eq!(chip8.program_counter(), 0x220);
+        assert_eq!(chip8.delay_timer(), 10);
+        assert_eq!(chip8.sound_timer(), 20);
+        assert_eq!(chip8.stack_pointer(), 0);
+    }
+
+    #[test]
+    fn builder_sets_the_fields() {
+        let quirks = Quirks {
+            shift_uses_vy: true,
+            ..Quirks::default()
+        };
+        let chip8 = CHIP8::builder()
+            .quirks(quirks)
+            .debug(true)
+            .memory_size(MAX_MEMORY_SIZE)
+            .build();
+
+        assert!(chip8.debug);
+        assert_eq!(chip8.quirks, quirks);
+        assert_eq!(chip8.memory.len(), MAX_MEMORY_SIZE);
+        assert!(CHIP8::default() == CHIP8::new());
+    }
+
+    #[test]
+    fn clones_run_the_same() {
+        let mut chip8 = CHIP8::builder()
+            .rng(Box::new(crate::rng::SeededRng::new(1)))
+            .build();
+        chip8.load_rom_bytes(&[0xC2, 0xFF]).unwrap(); // RND V2, 0xFF
+        let mut clone = chip8.clone();
+
+        chip8.cycle().unwrap();
+        clone.cycle().unwrap();
+        assert_eq!(chip8.vregister[2], clone.vregister[2]);
+    }
+
+    #[test]
+    fn bcd_near_the_end_of_memory_is_an_error() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0xF0, 0x33]).unwrap(); // Fx33
+        chip8.vregister[0] = 234;
+        chip8.index_register = 0xFFE;
+
+        assert_eq!(chip8.cycle(), Err(StepError::MemoryOutOfBounds(0xFFE)));
+        assert_eq!(chip8.memory[0xFFE..], [0, 0]);
+
+        chip8.program_counter = 0x200;
+        chip8.index_register = 0xFFD;
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.memory[0xFFD..], [2, 3, 4]);
+    }
+
+    #[test]
+    fn sprites_clip_or_wrap_at_the_right_edge() {
+        for wrap in [true, false] {
+            let mut chip8 = CHIP8::new();
+            chip8.quirks.wrap_x = wrap;
+            chip8.quirks.wrap_y = wrap;
+            // a full 8 pixel row at (60, 2)
+            chip8.load_rom_bytes(&[0xD0, 0x11]).unwrap();
+            chip8.memory[0x300] = 0xFF;
+            chip8.index_register = 0x300;
+            chip8.vregister[0] = 60;
+            chip8.vregister[1] = 2;
+            chip8.cycle().unwrap();
+
+            let row = &chip8.display[2 * CHIP8_WIDTH..3 * CHIP8_WIDTH];
+            assert_eq!(row[60..], [1, 1, 1, 1]);
+            assert_eq!(row[..4], [wrap as u8; 4]);
+            assert!(row[4..60].iter().all(|&pixel| pixel == 0));
+        }
+    }
+
+    #[test]
+    fn pressed_keys_are_seen_by_ex9e() {
+        let mut chip8 = CHIP8::new();
+        // LD V0, 0xA / SKP V0 / JP 0x200 (skipped) / the next instruction is at 0x206
+        chip8
+            .load_rom_bytes(&[0x60, 0x0A, 0xE0, 0x9E, 0x12, 0x00])
+            .unwrap();
+        assert!(chip8.press_key(0xA));
+        assert!(!chip8.press_key(0x10));
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.program_counter, 0x206);
+
+        assert!(chip8.release_key(0xA));
+        assert!(!chip8.keypad[0xA]);
+        chip8.set_keypad([true; 16]);
+        assert_eq!(chip8.keypad, [true; 16]);
+    }
+
+    #[test]
+    fn load_at_places_blobs_anywhere() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        chip8.load_at(&[1, 2, 3], 0x400).unwrap();
+        chip8.load_at(&[4, 5], 0xFFE).unwrap();
+
+        assert_eq!(chip8.memory[0x200..0x202], [0x12, 0x00]);
+        assert_eq!(chip8.memory[0x400..0x403], [1, 2, 3]);
+        assert_eq!(chip8.memory[0xFFE..], [4, 5]);
+        assert_eq!(chip8.rom_len(), 2); // overlays aren't part of the rom
+
+        assert!(matches!(
+            chip8.load_at(&[1, 2, 3], 0xFFE),
+            Err(RomError::TooLarge(3))
+        ));
+        assert!(matches!(
+            chip8.load_at(&[1], 0x1000),
+            Err(RomError::OutOfBounds(0x1000))
+        ));
+    }
+
+    #[test]
+    fn profiling_counts_the_loop_jumps() {
+        let mut chip8 = CHIP8::new();
+        // ADD V0, 1 / SE V0, 5 / JP 0x200: the jump runs 4 times before V0 reaches 5
+        chip8
+            .load_rom_bytes(&[0x70, 0x01, 0x30, 0x05, 0x12, 0x00])
+            .unwrap();
+        chip8.enable_profiling();
+        for _ in 0..14 {
+            chip8.cycle().unwrap();
+        }
+
+        let count =
+            |name| chip8.profile()[OPCODE_CATEGORIES.iter().position(|&c| c == name).unwrap()];
+        assert_eq!(count("JP nnn"), 4);
+        assert_eq!(count("ADD Vx, kk"), 5);
+        assert_eq!(count("SE Vx, kk"), 5);
+        assert_eq!(
+            chip8.profile_report(),
+            "SE Vx, kk: 5\nADD Vx, kk: 5\nJP nnn: 4\n"
+        );
+    }
+
+    #[test]
+    fn drawing_on_one_plane_leaves_the_other_alone() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_fonts();
+        // F201 (plane 2) / LD I, font 0 / DRW V0, V0, 5
+        chip8
+            .load_rom_bytes(&[0xF2, 0x01, 0xA0, 0x50, 0xD0, 0x05])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+
+        assert!(chip8.display.iter().all(|&pixel| pixel & 0x1 == 0));
+        assert_eq!(chip8.display[..4], [2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn collisions_come_from_every_selected_plane() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_fonts();
+        // F301 (both planes) / LD I, font 0 / DRW, drawing the 0 on plane 1 and the 1 on plane 2
+        // then F201 / DRW again, the 0 on plane 2 only, where it overlaps the 1
+        chip8
+            .load_rom_bytes(&[0xF3, 0x01, 0xA0, 0x50, 0xD0, 0x05, 0xF2, 0x01, 0xD0, 0x05])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.vregister[0xF], 0);
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.vregister[0xF], 1);
+    }
+
+    #[test]
+    fn two_plane_sprite_past_the_end_of_memory_draws_nothing() {
+        let mut chip8 = CHIP8::new();
+        // F301 / LD I, 0xFFB / DRW: 5 bytes fit, the second plane's 5 don't
+        chip8
+            .load_rom_bytes(&[0xF3, 0x01, 0xAF, 0xFB, 0xD0, 0x05])
+            .unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+
+        assert_eq!(chip8.cycle(), Err(StepError::OutOfBoundsSpriteRead(0xFFB)));
+        assert!(chip8.display.iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn jumping_to_itself_halts() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x60, 0x01, 0x12, 0x02]).unwrap(); // LD V0, 1 / JP 0x202
+        chip8.cycle().unwrap();
+        assert!(!chip8.is_halted());
+
+        chip8.cycle().unwrap();
+        assert!(chip8.is_halted());
+        assert_eq!(chip8.step(), Ok(StepOutcome::Halted));
+        assert_eq!(chip8.program_counter, 0x202);
+
+        chip8.reset();
+        assert!(!chip8.is_halted());
+    }
+
+    #[test]
+    fn hexdump_formats_rows_with_a_gutter() {
+        let mut chip8 = CHIP8::new();
+        chip8
+            .load_rom_bytes(&[
+                0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C, 0x61, 0x08, 0xD0, 0x1F, 0x70, 0x09, 0xA2, 0x39,
+                0xD0, 0x1F, b'C', b'H', b'I', b'P', b'-', b'8', b' ', b'!', 0, 0, 0, 0, 0, 0, 0, 0,
+            ])
+            .unwrap();
+
+        assert_eq!(
+            chip8.hexdump(0x200, 32),
+            "0200: 00 E0 A2 2A 60 0C 61 08 D0 1F 70 09 A2 39 D0 1F  |...*`.a...p..9..|\n\
+             0210: 43 48 49 50 2D 38 20 21 00 00 00 00 00 00 00 00  |CHIP-8 !........|\n"
+        );
+
+        // a short row is padded so the gutter lines up, and nothing is read past the end of memory
+        assert_eq!(
+            chip8.hexdump(0xFFE, 16),
+            format!("0FFE: 00 00{}  |..|\n", "   ".repeat(14))
+        );
+    }
+
+    #[test]
+    fn beeping_follows_the_sound_threshold() {
+        for (threshold, beeping) in [(0, true), (1, false)] {
+            let mut chip8 = CHIP8::new();
+            chip8.quirks.sound_threshold = threshold;
+            chip8.sound_timer = 1;
+            assert_eq!(chip8.is_beeping(), beeping);
+
+            chip8.sound_timer = 2;
+            assert!(chip8.is_beeping());
+        }
+    }
+
+    #[test]
+    fn run_frame_ticks_the_timers_once_whatever_the_speed() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // ADD V0, 1; JP 200
+        chip8.delay_timer = 10;
+        chip8.sound_timer = 10;
+
+        chip8.run_frame(1).unwrap();
+        assert_eq!((chip8.delay_timer, chip8.sound_timer), (9, 9));
+
+        chip8.run_frame(20).unwrap();
+        assert_eq!((chip8.delay_timer, chip8.sound_timer), (8, 8));
+    }
+
+    #[test]
+    fn dxy0_draws_a_16x16_sprite() {
+        let mut chip8 = CHIP8::new();
+        // I = 210, draw it twice at 0,0, then loop; the sprite is a full row over an outlined one
+        chip8
+            .load_rom_bytes(&[
+                0xA2, 0x10, 0xD0, 0x00, 0xD0, 0x00, 0x12, 0x06, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF,
+                0x80, 0x01,
+            ])
+            .unwrap();
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.vregister[0xF], 0);
+        assert_eq!(chip8.display[0..16], [1; 16]);
+        assert_eq!(chip8.display[CHIP8_WIDTH], 1);
+        assert_eq!(chip8.display[CHIP8_WIDTH + 1], 0);
+        assert_eq!(chip8.display[CHIP8_WIDTH + 15], 1);
+        assert_eq!(chip8.display[CHIP8_WIDTH + 16], 0);
+
+        // drawing it again erases it and collides
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.vregister[0xF], 1);
+        assert!(chip8.display.iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn fx30_points_at_the_big_digit() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_fonts();
+        chip8.load_rom_bytes(&[0x60, 0x03, 0xF0, 0x30]).unwrap(); // LD V0, 3; LD HF, V0
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+
+        let i = chip8.index_register as usize;
+        assert_eq!(i, BIG_FONT_ADDRESS + 30);
+        assert_eq!(
+            chip8.memory[i..i + 10],
+            [0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C]
+        );
+    }
+
+    #[test]
+    fn display_diff_marks_the_differing_pixels() {
+        let mut chip8 = CHIP8::new();
+        let mut expected = vec![0; CHIP8_WIDTH * CHIP8_HEIGHT];
+        assert_eq!(chip8.display_diff(&expected), None);
+        assert!(chip8.clone() == chip8);
+
+        chip8.display[3] = 1;
+        expected[CHIP8_WIDTH + 1] = 1;
+        let diff = chip8.display_diff(&expected).unwrap();
+        let map = diff
+            .split("differences (row numbers on the right):\n")
+            .nth(1)
+            .unwrap();
+        let mut rows = map.lines();
+        assert_eq!(rows.next().unwrap(), format!("...#{} 0", ".".repeat(60)));
+        assert_eq!(rows.next().unwrap(), format!(".#{} 1", ".".repeat(62)));
+        assert_eq!(rows.next().unwrap(), format!("{} 2", ".".repeat(64)));
+
+        assert!(diff.starts_with(&format!("actual:\n...#{}\n", ".".repeat(60))));
+        assert!(chip8.display_diff(&[0; 10]).is_some());
+        assert!(chip8 != CHIP8::new());
+    }
+
+    #[test]
+    fn machine_calls_are_skipped_unless_strict() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x01, 0x23, 0x01, 0x23]).unwrap(); // SYS 123 twice
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.program_counter, 0x202);
+
+        chip8.strict_machine_calls = true;
+        assert!(matches!(
+            chip8.cycle(),
+            Err(StepError::UnsupportedMachineCall(0x202))
+        ));
+    }
+
+    #[test]
+    fn fx55_and_fx65_copy_v0_through_vx() {
+        let mut chip8 = CHIP8::new();
+        // I = 300, store V0, store V0..VF, I = 300, load V0..VF
+        chip8
+            .load_rom_bytes(&[
+                0xA3, 0x00, 0xF0, 0x55, 0xA3, 0x00, 0xFF, 0x55, 0xA3, 0x00, 0xFF, 0x65,
+            ])
+            .unwrap();
+        for (i, v) in chip8.vregister.iter_mut().enumerate() {
+            *v = i as u8 + 1;
+        }
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.memory[0x300..0x302], [1, 0]);
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        let stored: Vec<u8> = (1..=16).collect();
+        assert_eq!(chip8.memory[0x300..0x310], stored[..]);
+        assert_eq!(chip8.memory[0x310], 0);
+
+        chip8.vregister = [0; 16];
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.vregister[..], stored[..]);
+    }
+
+    #[test]
+    fn fx55_and_fx65_stay_inside_memory() {
+        for opcode in [0x55, 0x65] {
+            // the last 16 bytes fit exactly
+            let mut chip8 = CHIP8::new();
+            chip8.load_rom_bytes(&[0xAF, 0xF0, 0xFF, opcode]).unwrap();
+            chip8.cycle().unwrap();
+            chip8.cycle().unwrap();
+
+            // one further would run off the end
+            let mut chip8 = CHIP8::new();
+            chip8.load_rom_bytes(&[0xAF, 0xF1, 0xFF, opcode]).unwrap();
+            chip8.cycle().unwrap();
+            assert!(matches!(
+                chip8.cycle(),
+                Err(StepError::MemoryOutOfBounds(0xFF1))
+            ));
+        }
+    }
+
+    #[test]
+    fn rom_hash_is_fnv_1a_of_the_rom() {
+        let mut chip8 = CHIP8::new();
+        assert_eq!(chip8.rom_hash(), 0xcbf29ce484222325); // nothing loaded
+
+        // the FNV-1a test vector, the zeros after the rom aren't part of it
+        chip8.load_rom_bytes(b"foobar").unwrap();
+        assert_eq!(chip8.rom_hash(), 0x85944171f73967e8);
+        chip8.load_rom_bytes(b"foobar\0").unwrap();
+        assert_ne!(chip8.rom_hash(), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn rom_len_and_end_address() {
+        let mut chip8 = CHIP8::new();
+        assert_eq!(chip8.rom_len(), 0);
+
+        chip8.load_rom_bytes(&[0xAB; 100]).unwrap();
+        assert_eq!(chip8.rom_len(), 100);
+        assert_eq!(chip8.rom_end_address(), 0x264);
+
+        chip8.load_rom_bytes(&[0xAB; 4]).unwrap();
+        assert_eq!(chip8.rom_len(), 4);
+    }
+
+    #[test]
+    fn strict_memory_protects_the_font() {
+        for strict in [true, false] {
+            let mut chip8 = CHIP8::new();
+            chip8.load_fonts();
+            chip8.strict_memory = strict;
+            chip8.vregister[0] = 0xAA;
+            chip8.load_rom_bytes(&[0xA0, 0x50, 0xF0, 0x55]).unwrap(); // I = 50; LD [I], V0
+            chip8.cycle().unwrap();
+
+            if strict {
+                assert!(matches!(
+                    chip8.cycle(),
+                    Err(StepError::ProtectedWrite(0x50))
+                ));
+                assert_eq!(chip8.memory[0x50], 0xF0); // the top of the 0
+            } else {
+                chip8.cycle().unwrap();
+                assert_eq!(chip8.memory[0x50], 0xAA);
+            }
+        }
+    }
+
+    #[test]
+    fn the_frame_hook_sees_every_drawn_frame() {
+        use alloc::sync::Arc;
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut chip8 = CHIP8::new();
+        let hook_calls = calls.clone();
+        chip8.set_frame_hook(Box::new(move |display, width, height| {
+            assert_eq!(display.len(), width * height);
+            hook_calls.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        // one instruction a frame: I = 20A, draw, V0 = 0, draw, loop forever
+        chip8
+            .load_rom_bytes(&[
+                0xA2, 0x0A, 0xD0, 0x11, 0x60, 0x00, 0xD0, 0x11, 0x12, 0x08, 0x80,
+            ])
+            .unwrap();
+        let mut drawn = 0;
+        for _ in 0..6 {
+            drawn += chip8.run_frame(1).unwrap() as usize;
+        }
+
+        assert_eq!(drawn, 2);
+        assert_eq!(calls.load(Ordering::SeqCst), drawn);
+    }
+
+    #[test]
+    fn computed_jumps_past_memory_are_errors() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x60, 0x10, 0xBF, 0xFF]).unwrap(); // LD V0, 10; JP V0, FFF
+        chip8.cycle().unwrap();
+        assert!(matches!(
+            chip8.cycle(),
+            Err(StepError::OutOfBoundsJump(0x100F))
+        ));
+
+        // the last whole instruction in memory is still a valid target
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x60, 0x0E, 0xBF, 0xF0]).unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.program_counter, 0xFFE);
+    }
+
+    #[test]
+    fn persistence_fades_pixels_out() {
+        let mut chip8 = CHIP8::new();
+        chip8.enable_persistence(100);
+        // I = 208, draw, draw again to erase it, loop
+        chip8
+            .load_rom_bytes(&[0xA2, 0x08, 0xD0, 0x11, 0xD0, 0x11, 0x12, 0x06, 0x80])
+            .unwrap();
+
+        chip8.run_frame(2).unwrap();
+        assert_eq!(chip8.persisted_framebuffer()[0], 255);
+
+        chip8.run_frame(1).unwrap();
+        assert_eq!(chip8.display[0], 0);
+        assert_eq!(chip8.persisted_framebuffer()[0], 155);
+        chip8.run_frame(1).unwrap();
+        chip8.run_frame(1).unwrap();
+        assert_eq!(chip8.persisted_framebuffer()[0], 0);
+
+        chip8.enable_persistence(1);
+        chip8.display[0] = 1;
+        chip8.run_frame(1).unwrap();
+        chip8.disable_persistence();
+        assert!(chip8.persisted_framebuffer().iter().all(|&i| i == 0));
+    }
+
+    #[test]
+    fn fx29_uses_a_custom_font() {
+        let font: [u8; 80] = core::array::from_fn(|i| i as u8 + 1);
+        let mut chip8 = CHIP8::new();
+        chip8.load_fonts();
+        chip8.set_font(&font);
+        chip8
+            .load_rom_bytes(&[0xF0, 0x29, 0x60, 0x0F, 0xF0, 0x29])
+            .unwrap(); // LD F, V0 for 0 and F
+
+        chip8.cycle().unwrap();
+        let i = chip8.index_register as usize;
+        assert_eq!(chip8.memory[i..i + 5], font[0..5]);
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        let i = chip8.index_register as usize;
+        assert_eq!(chip8.memory[i..i + 5], font[75..80]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn run_budget_stops_at_the_deadline() {
+        use std::time::Duration;
+
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // ADD V0, 1; JP 200
+        let result = chip8.run_budget(usize::MAX, Some(Instant::now() + Duration::from_millis(5)));
+        assert_eq!(result.reason, StopReason::Deadline);
+        assert!(result.cycles < usize::MAX);
+
+        // a deadline that has already passed runs nothing
+        let result = chip8.run_budget(10, Some(Instant::now()));
+        assert_eq!(
+            result,
+            RunResult {
+                cycles: 0,
+                reason: StopReason::Deadline
+            }
+        );
+        assert_eq!(
+            chip8.run_budget(10, None),
+            RunResult {
+                cycles: 10,
+                reason: StopReason::CycleLimit
+            }
+        );
+
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0xF0, 0x0A]).unwrap(); // LD V0, K
+        assert_eq!(
+            chip8.run_budget(10, None),
+            RunResult {
+                cycles: 1,
+                reason: StopReason::WaitingForKey
+            }
+        );
+        chip8.halted = true;
+        assert_eq!(
+            chip8.run_budget(10, None),
+            RunResult {
+                cycles: 0,
+                reason: StopReason::Halted
+            }
+        );
+    }
+
+    #[test]
+    fn coverage_leaves_skipped_code_unexecuted() {
+        let mut chip8 = CHIP8::new();
+        chip8.enable_coverage();
+        // SE V0, 0 skips LD V0, 1 and lands on JP 204, with data after it
+        chip8
+            .load_rom_bytes(&[0x30, 0x00, 0x60, 0x01, 0x12, 0x04, 0xAA, 0xBB])
+            .unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+
+        assert_eq!(
+            chip8.coverage_report(),
+            "0200-0201 executed\n\
+             0202-0203 not executed\n\
+             0204-0205 executed\n\
+             0206-0207 not executed\n"
+        );
+    }
+
+    #[test]
+    fn known_roms_get_their_profile() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_fonts();
+        chip8
+            .load_rom_bytes(include_bytes!("../roms/c8games/BLITZ"))
+            .unwrap();
+        assert_eq!(chip8.apply_profile_for_loaded_rom(), Some("BLITZ"));
+        assert!(chip8.quirks.display_wait);
+
+        // anything else falls back to the defaults
+        chip8
+            .load_rom_bytes(include_bytes!("../roms/IBM Logo.ch8"))
+            .unwrap();
+        assert_eq!(chip8.apply_profile_for_loaded_rom(), None);
+        assert!(chip8.quirks == Quirks::default());
+    }
+
+    #[test]
+    fn call_stack_lists_nested_calls() {
+        let mut chip8 = CHIP8::new();
+        // CALL 204 from 200, CALL 208 from 204, which returns
+        chip8
+            .load_rom_bytes(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x08, 0x00, 0x00, 0x00, 0xEE])
+            .unwrap();
+        assert!(chip8.call_stack().is_empty());
+
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.call_stack(), [0x202, 0x206]);
+        assert_eq!(chip8.stack_depth(), 2);
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.call_stack(), [0x202]);
+    }
+
+    #[test]
+    fn trace_run_lists_the_instructions_run() {
+        use Instruction::*;
+
+        let mut chip8 = CHIP8::new();
+        chip8
+            .load_rom_bytes(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x04])
+            .unwrap(); // ... JP 204
+        assert_eq!(
+            chip8.trace_run(10),
+            [
+                (0x200, LoadImm { x: 0, kk: 5 }),
+                (0x202, AddImm { x: 0, kk: 1 }),
+                (0x204, Jump(0x204)), // jumping to itself halts, which ends the trace
+            ]
+        );
+        assert_eq!(chip8.vregister[0], 6);
+    }
+
+    #[test]
+    fn fx1e_sets_vf_only_with_the_quirk() {
+        // quirk, I, VF afterwards (9 is what VF held before)
+        for (sets_vf, i, vf) in [
+            (true, 0xFFF, 1),
+            (true, 0x100, 0),
+            (false, 0xFFF, 9),
+            (false, 0x100, 9),
+        ] {
+            let mut chip8 = CHIP8::new();
+            chip8.quirks.fx1e_sets_vf = sets_vf;
+            chip8
+                .load_rom_bytes(&[0x6F, 0x09, 0x60, 0x02, 0xF0, 0x1E])
+                .unwrap(); // ...; ADD I, V0
+            chip8.set_index_register(i);
+            for _ in 0..3 {
+                chip8.cycle().unwrap();
+            }
+
+            assert_eq!(chip8.vregister[0xF], vf);
+            assert_eq!(chip8.index_register(), i + 2);
+        }
+
+        // the sum can't overflow I itself
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x60, 0x02, 0xF0, 0x1E]).unwrap();
+        chip8.set_index_register(0xFFFF);
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.index_register(), 1);
+    }
+
+    #[test]
+    fn packed_framebuffer_round_trips() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_fonts();
+        // draw the 0 glyph at 0,0 and the F glyph at 59,27
+        chip8
+            .load_rom_bytes(&[
+                0xD0, 0x05, 0x61, 0x0F, 0xF1, 0x29, 0x62, 0x3B, 0x63, 0x1B, 0xD2, 0x35,
+            ])
+            .unwrap();
+        chip8.set_index_register(0x50);
+        for _ in 0..6 {
+            chip8.cycle().unwrap();
+        }
+
+        let packed = chip8.framebuffer_packed();
+        assert_eq!(packed.len(), 256);
+        assert_eq!(packed[0], 0xF0); // the top row of the 0
+        let drawn = chip8.display.clone();
+
+        chip8.display.fill(0);
+        assert!(!chip8.set_framebuffer_packed(&packed[..255]));
+        assert!(chip8.display.iter().all(|&pixel| pixel == 0));
+        assert!(chip8.set_framebuffer_packed(&packed));
+        assert_eq!(chip8.display, drawn);
+    }
+
+    #[test]
+    fn keys_past_f_are_never_pressed() {
+        let mut chip8 = CHIP8::new();
+        chip8.keypad = [true; 16];
+        // LD V0, 20; SKP V0; SKNP V0
+        chip8
+            .load_rom_bytes(&[0x60, 0x20, 0xE0, 0x9E, 0xE0, 0xA1])
+            .unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.program_counter, 0x204);
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.program_counter, 0x208);
+    }
+
+    #[test]
+    fn scrolling_needs_super_chip() {
+        let mut chip8 = CHIP8::builder().variant(Variant::Chip8).build();
+        chip8.load_rom_bytes(&[0x00, 0xC2]).unwrap(); // SCD 2
+        assert_eq!(chip8.cycle(), Err(StepError::UnsupportedInVariant(0x200)));
+
+        // so is the 16x16 sprite, an ordinary sprite is fine
+        let mut chip8 = CHIP8::builder().variant(Variant::Chip8).build();
+        chip8.load_rom_bytes(&[0xD0, 0x11, 0xD0, 0x10]).unwrap(); // DRW V0, V0, 1; DRW V0, V0, 0
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.cycle(), Err(StepError::UnsupportedInVariant(0x202)));
+
+        let mut chip8 = CHIP8::new();
+        chip8.set_variant(Variant::SuperChip);
+        assert!(chip8.quirks == Quirks::super_chip());
+        chip8.load_rom_bytes(&[0x00, 0xC2, 0xF0, 0x01]).unwrap(); // SCD 2; PLANE 0 (XO-CHIP)
+        chip8.display[0] = 1;
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.display[0], 0);
+        assert_eq!(chip8.display[2 * CHIP8_WIDTH], 1);
+        assert_eq!(chip8.cycle(), Err(StepError::UnsupportedInVariant(0x202)));
+    }
+
+    #[test]
+    fn run_until_draw_stops_after_the_draw() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_fonts();
+        // three register loads, DRW V0, V0, 5, then JP 208 halts
+        chip8
+            .load_rom_bytes(&[0x60, 0x01, 0x60, 0x02, 0x60, 0x03, 0xD0, 0x05, 0x12, 0x08])
+            .unwrap();
+
+        assert_eq!(chip8.run_until_draw(100), Ok(true));
+        assert_eq!(chip8.program_counter, 0x208);
+        assert_eq!(chip8.instructions_executed(), 4);
+        assert_eq!(chip8.run_until_draw(100), Ok(false));
+        assert!(chip8.is_halted());
+
+        let mut chip8 = CHIP8::new();
+        chip8
+            .load_rom_bytes(&[0x70, 0x01, 0xF0, 0x0A, 0x00, 0xE0])
+            .unwrap(); // ...; LD V0, K; CLS
+        assert_eq!(chip8.run_until_draw(100), Ok(false));
+        assert_eq!(chip8.program_counter, 0x202);
+    }
+
+    #[test]
+    fn adversarial_roms_end_in_errors() {
+        for rom in [
+            &[0x22, 0x00][..],         // calls itself until the stack overflows
+            &[0x00, 0xEE],             // returns with nothing on the stack
+            &[0xAF, 0xFF, 0xD0, 0x0F], // sprite read past the end of memory
+            &[0xAF, 0xFF, 0xF0, 0x33], // BCD past the end of memory
+            &[0xAF, 0xF1, 0xFF, 0x55], // register store past the end of memory
+            &[0xAF, 0xF1, 0xFF, 0x65], // register load past the end of memory
+            &[0x60, 0xFF, 0xBF, 0xFF], // computed jump past the end of memory
+            &[0x1F, 0xFE],             // runs off the end of memory
+        ] {
+            assert!(run_fuzz(rom, 10_000).is_err(), "{:02X?}", rom);
+        }
+
+        // roms too big to load never run
+        assert!(run_fuzz(&[0; 5000], 10).is_ok());
+    }
+
+    #[test]
+    fn random_roms_dont_panic() {
+        // xorshift, so the same roms are tried every time
+        let mut state: u64 = 0x1234567;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..500 {
+            let len = (next() % 600) as usize;
+            let rom: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            let _ = run_fuzz(&rom, 2000);
+        }
+    }
+
+    #[test]
+    fn builder_sets_up_a_machine_ready_to_step() {
+        let mut registers = [0; 16];
+        registers[2] = 5;
+        registers[3] = 7;
+        let mut chip8 = CHIP8Builder::new()
+            .registers(registers)
+            .poke_opcode(0x200, 0x8234) // ADD V2, V3
+            .build();
+
+        chip8.step().unwrap();
+        assert_eq!(chip8.vregister[2], 12);
+        assert_eq!(chip8.vregister[0xF], 0);
+        assert_eq!(chip8.program_counter, 0x202);
+    }
+
+    #[test]
+    fn render_ascii_draws_the_glyph_rows() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_fonts();
+        chip8.load_rom_bytes(&[0xA0, 0x50, 0xD0, 0x05]).unwrap(); // the 0 glyph at 0,0
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+
+        let ascii = chip8.render_ascii();
+        let rows: Vec<&str> = ascii.lines().collect();
+        assert_eq!(rows.len(), CHIP8_HEIGHT);
+        assert!(rows.iter().all(|row| row.chars().count() == CHIP8_WIDTH));
+        for (row, glyph) in rows
+            .iter()
+            .zip(["████", "█  █", "█  █", "█  █", "████", "    "])
+        {
+            assert!(row.starts_with(glyph), "{:?}", row);
+        }
+
+        let ascii = chip8.render_ascii_with('#', '.');
+        assert!(ascii.starts_with(&format!("####{}\n#..#", ".".repeat(60))));
+    }
+
+    #[test]
+    fn vf_reports_a_collision_on_any_row() {
+        for (lit, collides) in [
+            (None, false),
+            (Some(0), true),
+            (Some(4 * CHIP8_WIDTH), true),
+        ] {
+            let mut chip8 = CHIP8::new();
+            chip8.load_fonts();
+            chip8.vregister[0xF] = 1; // cleared unless something collides
+            if let Some(pixel) = lit {
+                chip8.display[pixel] = 1; // under the first or the last row of the 0
+            }
+            chip8.load_rom_bytes(&[0xA0, 0x50, 0xD0, 0x05]).unwrap();
+            chip8.cycle().unwrap();
+            chip8.cycle().unwrap();
+
+            assert_eq!(chip8.vregister[0xF], collides as u8, "{:?}", lit);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn machines_round_trip_through_json() {
+        let mut chip8 = CHIP8::builder().variant(Variant::SuperChip).build();
+        chip8.load_fonts();
+        chip8
+            .load_rom_bytes(include_bytes!("../roms/IBM Logo.ch8"))
+            .unwrap();
+        chip8.run_frame(20).unwrap();
+
+        let json = serde_json::to_string(&chip8).unwrap();
+        let restored: CHIP8 = serde_json::from_str(&json).unwrap();
+        assert!(restored == chip8);
+        assert!(restored.quirks == Quirks::super_chip());
+    }
+
+    #[test]
+    fn the_display_matches_the_active_resolution() {
+        let mut chip8 = CHIP8::new();
+        assert_eq!(chip8.display.len(), CHIP8_WIDTH * CHIP8_HEIGHT);
+        assert_eq!(
+            (chip8.display_width(), chip8.display_height()),
+            (CHIP8_WIDTH, CHIP8_HEIGHT)
+        );
+
+        chip8.load_rom_bytes(&[0x00, 0xFF, 0x00, 0xFE]).unwrap(); // HIGH; LOW
+        chip8.cycle().unwrap();
+        assert_eq!(
+            chip8.display.len(),
+            chip8.display_width() * chip8.display_height()
+        );
+        assert_eq!(chip8.display_width(), 2 * CHIP8_WIDTH);
+
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.display.len(), CHIP8_WIDTH * CHIP8_HEIGHT);
+    }
+
+    #[test]
+    fn overwriting_executed_code_is_reported() {
+        use alloc::sync::Arc;
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        // I = 200; V0 = 12; LD [I], V0 writes over the LD I, 200 that already ran
+        let rom = [0xA2, 0x00, 0x60, 0x12, 0xF0, 0x55];
+        let expected = CodeOverwrite {
+            pc: 0x204,
+            addr: 0x200,
+        };
+
+        let warnings = Arc::new(AtomicUsize::new(0));
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        chip8.enable_self_modify_checks();
+        let hook_warnings = warnings.clone();
+        chip8.set_overwrite_hook(Box::new(move |overwrite| {
+            assert_eq!(overwrite, expected);
+            hook_warnings.fetch_add(1, Ordering::SeqCst);
+        }));
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(warnings.load(Ordering::SeqCst), 1);
+        assert_eq!(chip8.code_overwrites(), [expected]);
+
+        // writing over code that hasn't run yet, or without the checks, isn't reported
+        let mut chip8 = CHIP8::new();
+        chip8
+            .load_rom_bytes(&[0xA2, 0x08, 0xF0, 0x33, 0x12, 0x04, 0x00, 0x00, 0x00, 0x00])
+            .unwrap();
+        chip8.enable_self_modify_checks();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert!(chip8.code_overwrites().is_empty());
+
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        assert!(chip8.code_overwrites().is_empty());
+    }
+
+    #[test]
+    fn i_wraps_at_0x1000_only_in_classic_mode() {
+        // LD V0, 2; ADD I, V0; LD I, LONG 1234
+        let rom = [0x60, 0x02, 0xF0, 0x1E, 0xF0, 0x00, 0x12, 0x34];
+
+        for (quirks, added, long) in [
+            (Quirks::cosmac_vip(), 0x001, 0x234),
+            (Quirks::xo_chip(), 0x1001, 0x1234),
+        ] {
+            let mut chip8 = CHIP8::builder().quirks(quirks).build();
+            chip8.load_rom_bytes(&rom).unwrap();
+            chip8.set_index_register(0xFFF);
+            chip8.cycle().unwrap();
+            chip8.cycle().unwrap();
+            assert_eq!(chip8.index_register(), added);
+
+            chip8.cycle().unwrap();
+            assert_eq!(chip8.index_register(), long);
+        }
+    }
+
+    #[test]
+    fn diff_run_finds_the_shift_quirk() {
+        // LD V0, 3; LD V1, 8; SHR V0, V1; JP 206
+        let rom = [0x60, 0x03, 0x61, 0x08, 0x80, 0x16, 0x12, 0x06];
+        let mut a = CHIP8::new();
+        a.load_rom_bytes(&rom).unwrap();
+        let mut b = a.clone();
+        a.quirks.shift_uses_vy = true;
+        b.quirks.shift_uses_vy = false;
+
+        let report = diff_run(&mut a, &mut b, 100).unwrap();
+        assert_eq!((report.cycle, report.pc), (3, 0x204));
+        assert_eq!(report.difference, Difference::Register(0));
+
+        // identical machines run to the halt together
+        let mut c = b.clone();
+        assert_eq!(diff_run(&mut b, &mut c, 100), None);
+    }
+
+    #[cfg(all(feature = "log", feature = "std"))]
+    #[test]
+    fn instructions_log_what_they_do() {
+        use std::sync::Mutex;
+
+        // every record goes here, other tests running at the same time may add theirs
+        static RECORDS: Mutex<Vec<(log::Level, String)>> = Mutex::new(Vec::new());
+        struct Capture;
+        impl log::Log for Capture {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+            fn log(&self, record: &log::Record) {
+                let message = (record.level(), record.args().to_string());
+                RECORDS.lock().unwrap().push(message);
+            }
+            fn flush(&self) {}
+        }
+        log::set_logger(&Capture).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut chip8 = CHIP8::new();
+        chip8.debug = true;
+        chip8.load_rom_bytes(&[0x60, 0x05]).unwrap(); // LD V0, 5
+        chip8.cycle().unwrap();
+
+        let records = RECORDS.lock().unwrap();
+        assert!(records.iter().any(
+            |(level, message)| *level == log::Level::Trace && message.ends_with("Opcode: 6005")
+        ));
+        assert!(records.contains(&(log::Level::Debug, "setting register V0 to 5".into())));
+    }
+
+    #[test]
+    fn cheats_patch_the_rom() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0; 16]).unwrap();
+        chip8.apply_cheat("204:FF").unwrap();
+        assert_eq!(chip8.memory[0x204], 0xFF);
+
+        for (cheat, error) in [
+            ("204FF", CheatError::Malformed),
+            ("20G:FF", CheatError::Malformed),
+            ("204:100", CheatError::Malformed),
+            ("1FF:00", CheatError::OutsideRom(0x1FF)),
+            ("210:00", CheatError::OutsideRom(0x210)),
+        ] {
+            assert_eq!(chip8.apply_cheat(cheat), Err(error), "{}", cheat);
+        }
+
+        // a bad cheat in a list means none of them are applied
+        assert!(chip8.apply_cheats(&["200:01", "x"]).is_err());
+        assert_eq!(chip8.memory[0x200], 0);
+        chip8.apply_cheats(&["200:01", " 20F:02 "]).unwrap();
+        assert_eq!((chip8.memory[0x200], chip8.memory[0x20F]), (1, 2));
+    }
+
+    #[test]
+    fn sprites_read_past_memory_are_errors() {
+        // I = FF8, DRW V0, V0, F: 15 rows from FF8 run past FFF
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0xAF, 0xF8, 0xD0, 0x0F]).unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.cycle(), Err(StepError::OutOfBoundsSpriteRead(0xFF8)));
+        assert!(chip8.display.iter().all(|&pixel| pixel == 0));
+
+        // ending on the last byte is fine
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0xAF, 0xF1, 0xD0, 0x0F]).unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+    }
+
+    #[test]
+    fn frozen_timers_dont_tick() {
+        let mut chip8 = CHIP8::new();
+        chip8.delay_timer = 10;
+        chip8.sound_timer = 10;
+
+        chip8.freeze_timers(true);
+        assert!(chip8.timers_frozen());
+        for _ in 0..5 {
+            chip8.tick_timers();
+        }
+        chip8.run_frame(1).unwrap();
+        assert_eq!((chip8.delay_timer, chip8.sound_timer), (10, 10));
+
+        chip8.freeze_timers(false);
+        chip8.tick_timers();
+        assert_eq!((chip8.delay_timer, chip8.sound_timer), (9, 9));
+    }
+
+    #[test]
+    fn display_shows_a_status_line() {
+        let mut chip8 = CHIP8::new();
+        // LD V0, 2A; CALL 206; ...; LD I, 300
+        chip8
+            .load_rom_bytes(&[0x60, 0x2A, 0x22, 0x06, 0x00, 0x00, 0xA3, 0x00])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.cycle().unwrap();
+        }
+        chip8.delay_timer = 7;
+
+        assert_eq!(
+            format!("{}", chip8),
+            "PC=0x208 I=0x300 SP=1 DT=7 ST=0 cycles=3"
+        );
+        assert_eq!(
+            format!("{:#}", chip8),
+            "PC=0x208 I=0x300 SP=1 DT=7 ST=0 cycles=3 \
+             V=[2A 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00]"
+        );
+    }
+
+    #[test]
+    fn resolution_switches_resize_the_display() {
+        for clears in [true, false] {
+            let mut chip8 = CHIP8::new();
+            chip8.quirks.resolution_switch_clears = clears;
+            chip8.memory[0x300] = 0x80; // a single pixel
+
+            // I = 300, a dot at 36,8, HIGH, a dot at 100,40, LOW, the dot at 36,8 again
+            chip8
+                .load_rom_bytes(&[
+                    0xA3, 0x00, 0x60, 0x24, 0x61, 0x08, 0xD0, 0x11, 0x00, 0xFF, 0x62, 0x64, 0x63,
+                    0x28, 0xD2, 0x31, 0x00, 0xFE, 0xD0, 0x11,
+                ])
+                .unwrap();
+            for _ in 0..4 {
+                chip8.cycle().unwrap();
+            }
+            assert_eq!(chip8.display[8 * CHIP8_WIDTH + 36], 1);
+
+            chip8.cycle().unwrap();
+            assert_eq!(chip8.display.len(), 4 * CHIP8_WIDTH * CHIP8_HEIGHT);
+            assert_eq!(chip8.display[8 * 2 * CHIP8_WIDTH + 36], !clears as u8);
+            for _ in 0..3 {
+                chip8.cycle().unwrap();
+            }
+            assert_eq!(chip8.display[40 * 2 * CHIP8_WIDTH + 100], 1);
+
+            chip8.cycle().unwrap();
+            assert_eq!(chip8.display.len(), CHIP8_WIDTH * CHIP8_HEIGHT);
+            assert_eq!(chip8.display[8 * CHIP8_WIDTH + 36], !clears as u8);
+
+            // drawn again, it either comes back or erases what was kept, with a collision
+            chip8.cycle().unwrap();
+            assert_eq!(chip8.display[8 * CHIP8_WIDTH + 36], clears as u8);
+            assert_eq!(chip8.vregister[0xF], !clears as u8);
+        }
+    }
+
+    #[test]
+    fn unimplemented_opcodes_are_listed() {
+        // 00FF in a plain CHIP-8 machine, which doesn't have SUPER-CHIP's high res
+        let mut chip8 = CHIP8::builder().variant(Variant::Chip8).build();
+        chip8.enable_unimplemented_tracking();
+        chip8.load_rom_bytes(&[0x00, 0xFF]).unwrap();
+        assert_eq!(chip8.cycle(), Err(StepError::UnsupportedInVariant(0x200)));
+        assert_eq!(chip8.unimplemented_opcodes(), [0x00FF]);
+
+        // unknown opcodes are listed once each, in order, while the skip policy carries on past them
+        let mut chip8 = CHIP8::new();
+        chip8.enable_unimplemented_tracking();
+        chip8.on_unknown_opcode = UnknownOpcodePolicy::Skip;
+        chip8
+            .load_rom_bytes(&[0x50, 0x1F, 0xE0, 0x00, 0x50, 0x1F, 0x12, 0x06])
+            .unwrap();
+        for _ in 0..4 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.unimplemented_opcodes(), [0x501F, 0xE000]);
     }
 }
@@ -0,0 +1,54 @@
+// small public domain roms built into the binary, so there's something to run without a rom file
+
+pub struct Demo {
+    pub name: &'static str, // what --demo takes
+    pub title: &'static str,
+    pub rom: &'static [u8],
+}
+
+// the first one runs when no game is given
+pub const DEMOS: [Demo; 2] = [
+    Demo {
+        name: "maze",
+        title: "Maze, by David Winter",
+        rom: include_bytes!("../roms/Maze [David Winter, 199x].ch8"),
+    },
+    Demo {
+        name: "particles",
+        title: "Particle Demo, by zeroZshadow (2008)",
+        rom: include_bytes!("../roms/Particle Demo [zeroZshadow, 2008].ch8"),
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Demo> {
+    DEMOS
+        .iter()
+        .find(|demo| demo.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_demo_runs_and_draws() {
+        use chip8::CHIP8;
+
+        for demo in &DEMOS {
+            let mut chip8 = CHIP8::new();
+            chip8.load_fonts();
+            chip8.load_rom_bytes(demo.rom).unwrap();
+
+            for _ in 0..120 {
+                chip8.run_frame(10).unwrap();
+            }
+            assert!(chip8.display.contains(&1), "{} drew nothing", demo.name);
+        }
+    }
+
+    #[test]
+    fn find_ignores_case() {
+        assert_eq!(find("MAZE").unwrap().name, "maze");
+        assert!(find("pong").is_none());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::chip8::{StepError, CHIP8};
+
+// 0xAARRGGBB colors for each display value: off, plane 1, plane 2, both planes
+pub const DEFAULT_PALETTE: [u32; 4] = [0xFF000000, 0xFFFFFFFF, 0xFFAAAAAA, 0xFF555555];
+
+// nearest-neighbour upscale of a display (w * h pixels, row by row) into buffer, which has to hold
+// w * scale * h * scale colors: lit pixels become fg and unlit ones bg
+pub fn scale_into(
+    display: &[u8],
+    w: usize,
+    h: usize,
+    buffer: &mut [u32],
+    scale: usize,
+    fg: u32,
+    bg: u32,
+) {
+    scale_into_palette(display, w, h, buffer, scale, &[bg, fg, fg, fg]);
+}
+
+// the same with a color for each display value, like DEFAULT_PALETTE
+pub fn scale_into_palette(
+    display: &[u8],
+    w: usize,
+    h: usize,
+    buffer: &mut [u32],
+    scale: usize,
+    palette: &[u32; 4],
+) {
+    scale_into_with(display, w, h, buffer, scale, |pixel| {
+        palette[pixel as usize & 0x3]
+    });
+}
+
+// the same with any mapping from pixel values to colors, for frontends whose pixels hold more than
+// the plane bits (like a brightness for fading)
+pub fn scale_into_with(
+    display: &[u8],
+    w: usize,
+    h: usize,
+    buffer: &mut [u32],
+    scale: usize,
+    color: impl Fn(u8) -> u32,
+) {
+    let line = w * scale;
+
+    for y in 0..h {
+        // fill in the first line of each row a pixel at a time, the rest of the row are copies of it
+        let top = y * scale * line;
+        for (x, &pixel) in display[y * w..(y + 1) * w].iter().enumerate() {
+            let start = top + x * scale;
+            buffer[start..start + scale].fill(color(pixel));
+        }
+
+        for copy in 1..scale {
+            buffer.copy_within(top..top + line, top + copy * line);
+        }
+    }
+}
+
+// anything that can show the chip8 display and feed it keys (a window, a terminal, a test harness...)
+pub trait Frontend {
+    // show a frame, display is width * height pixels, row by row
+    // (0 is off, 1-3 are the XO-CHIP plane combinations, see DEFAULT_PALETTE)
+    fn present(&mut self, display: &[u8], width: usize, height: usize);
+    // current state of the 16-key hexadecimal keypad
+    fn poll_keys(&mut self) -> [bool; 16];
+    fn should_close(&self) -> bool;
+}
+
+// headless frontend that draws nothing and closes after a fixed number of frames
+pub struct NullFrontend {
+    pub keys: [bool; 16],
+    pub frames: usize, // frames presented so far
+    pub max_frames: usize,
+}
+
+impl NullFrontend {
+    pub fn new(max_frames: usize) -> Self {
+        Self {
+            keys: [false; 16],
+            frames: 0,
+            max_frames,
+        }
+    }
+}
+
+impl Frontend for NullFrontend {
+    fn present(&mut self, _display: &[u8], _width: usize, _height: usize) {
+        self.frames += 1;
+    }
+
+    fn poll_keys(&mut self) -> [bool; 16] {
+        self.keys
+    }
+
+    fn should_close(&self) -> bool {
+        self.frames >= self.max_frames
+    }
+}
+
+// frames are presented at 60Hz, this many instructions run per frame (~600 instructions per second)
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 10;
+
+// run the machine until the frontend wants to close or the emulation fails
+// each frontend frame runs `ipf` cycles and ticks the timers once
+pub fn run<F: Frontend>(chip8: &mut CHIP8, frontend: &mut F, ipf: usize) -> Result<(), StepError> {
+    while !frontend.should_close() {
+        frontend.present(
+            &chip8.display,
+            chip8.display_width(),
+            chip8.display_height(),
+        );
+        chip8.keypad = frontend.poll_keys();
+
+        chip8.run_frame(ipf)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn scale_into_scales_a_checkerboard() {
+        let mut buffer = vec![0; 6 * 6];
+        scale_into(&[1, 0, 0, 1], 2, 2, &mut buffer, 3, 0xFFFFFFFF, 0xFF000000);
+
+        for y in 0..6 {
+            for x in 0..6 {
+                let lit = (x / 3 + y / 3) % 2 == 0;
+                let expected = if lit { 0xFFFFFFFF } else { 0xFF000000 };
+                assert_eq!(buffer[y * 6 + x], expected, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn scale_into_with_maps_every_value() {
+        let mut buffer = vec![0; 2];
+        scale_into_with(&[7, 200], 2, 1, &mut buffer, 1, |pixel| pixel as u32 * 2);
+        assert_eq!(buffer, [14, 400]);
+    }
+
+    #[test]
+    fn run_drives_a_null_frontend() {
+        let mut chip8 = CHIP8::new();
+        // ADD V0, 1 / JP 0x200
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip8.keypad[3] = true;
+        let mut frontend = NullFrontend::new(5);
+
+        run(&mut chip8, &mut frontend, 10).unwrap();
+
+        assert_eq!(frontend.frames, 5);
+        assert_eq!(chip8.vregister[0], 25); // 5 frames of 10 instructions, half of them adds
+        assert!(!chip8.keypad[3]); // the frontend's (empty) keypad replaced it
+    }
+}
@@ -0,0 +1,58 @@
+use gilrs::{Button, Gilrs};
+
+// controller buttons and the keypad key each one presses, covering the usual
+// 2/4/6/8 movement keys plus 5 and F which most games use for fire/action
+pub const DEFAULT_MAPPING: [(Button, usize); 6] = [
+    (Button::DPadUp, 0x2),
+    (Button::DPadLeft, 0x4),
+    (Button::DPadRight, 0x6),
+    (Button::DPadDown, 0x8),
+    (Button::South, 0x5),
+    (Button::East, 0xF),
+];
+
+pub struct Gamepad {
+    gilrs: Option<Gilrs>, // None when gamepad support couldn't be initialized
+    pub mapping: Vec<(Button, usize)>,
+}
+
+impl Gamepad {
+    pub fn new() -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => {
+                println!(
+                    "gamepad support unavailable, using the keyboard only: {}",
+                    e
+                );
+                None
+            }
+        };
+
+        Self {
+            gilrs,
+            mapping: DEFAULT_MAPPING.to_vec(),
+        }
+    }
+
+    // press the mapped keys for every button held on any connected controller,
+    // on top of whatever the keyboard already pressed
+    pub fn poll(&mut self, keypad: &mut [bool; 16]) {
+        let Some(gilrs) = &mut self.gilrs else {
+            return;
+        };
+
+        // gilrs only updates the button state while its events are drained
+        while gilrs.next_event().is_some() {}
+
+        for (_, gamepad) in gilrs.gamepads() {
+            for &(button, key) in &self.mapping {
+                if gamepad.is_pressed(button) {
+                    if let Some(pressed) = keypad.get_mut(key) {
+                        *pressed = true;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use core::fmt;
+
+// a decoded instruction, x and y are register numbers (0-15)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Sys(u16),                              // 0nnn: call a machine code routine (ignored)
+    Cls,                                   // 00E0: clear the display
+    Ret,                                   // 00EE: return from a subroutine
+    ScrollDown(u8),                        // 00CN: SUPER-CHIP scroll the display down N pixels
+    ScrollUp(u8),                          // 00DN: XO-CHIP scroll the display up N pixels
+    ScrollRight,                           // 00FB: SUPER-CHIP scroll the display right 4 pixels
+    ScrollLeft,                            // 00FC: SUPER-CHIP scroll the display left 4 pixels
+    Exit,                                  // 00FD: SUPER-CHIP exit the interpreter
+    LowRes,                                // 00FE: SUPER-CHIP switch to the 64x32 display
+    HighRes,                               // 00FF: SUPER-CHIP switch to the 128x64 display
+    Jump(u16),                             // 1nnn: jump to nnn
+    Call(u16),                             // 2nnn: call the subroutine at nnn
+    SkipEqImm { x: usize, kk: u8 },        // 3xkk: skip if Vx == kk
+    SkipNeImm { x: usize, kk: u8 },        // 4xkk: skip if Vx != kk
+    SkipEqReg { x: usize, y: usize },      // 5xy0: skip if Vx == Vy
+    SaveRange { x: usize, y: usize },      // 5xy2: XO-CHIP store Vx..Vy at I
+    LoadRange { x: usize, y: usize },      // 5xy3: XO-CHIP read Vx..Vy from I
+    LoadImm { x: usize, kk: u8 },          // 6xkk: Vx = kk
+    AddImm { x: usize, kk: u8 },           // 7xkk: Vx += kk, wraps and leaves VF alone
+    Move { x: usize, y: usize },           // 8xy0: Vx = Vy
+    Or { x: usize, y: usize },             // 8xy1: Vx |= Vy
+    And { x: usize, y: usize },            // 8xy2: Vx &= Vy
+    Xor { x: usize, y: usize },            // 8xy3: Vx ^= Vy
+    Add { x: usize, y: usize },            // 8xy4: Vx += Vy, VF = carry
+    Sub { x: usize, y: usize },            // 8xy5: Vx -= Vy, VF = NOT borrow
+    ShiftRight { x: usize, y: usize },     // 8xy6: Vx = Vx (or Vy) >> 1, VF = shifted out bit
+    SubN { x: usize, y: usize },           // 8xy7: Vx = Vy - Vx, VF = NOT borrow
+    ShiftLeft { x: usize, y: usize },      // 8xyE: Vx = Vx (or Vy) << 1, VF = shifted out bit
+    SkipNeReg { x: usize, y: usize },      // 9xy0: skip if Vx != Vy
+    LoadI(u16),                            // Annn: I = nnn
+    JumpOffset { x: usize, nnn: u16 },     // Bnnn: jump to nnn + V0 (or Vx with the jump quirk)
+    Random { x: usize, kk: u8 },           // Cxkk: Vx = random byte AND kk
+    Draw { x: usize, y: usize, n: usize }, // Dxyn: draw an n-byte sprite from I at (Vx, Vy), 16x16 when n is 0
+    SkipKey { x: usize },                  // Ex9E: skip if key Vx is pressed
+    SkipNotKey { x: usize },               // ExA1: skip if key Vx is not pressed
+    LoadLongI,                             // F000: XO-CHIP I = the word after this instruction
+    SelectPlane(u8),                       // Fx01: XO-CHIP select the drawing planes
+    LoadAudio,                             // F002: XO-CHIP load the audio pattern from I
+    LoadDelay { x: usize },                // Fx07: Vx = delay timer
+    WaitKey { x: usize },                  // Fx0A: wait for a key press, store it in Vx
+    SetDelay { x: usize },                 // Fx15: delay timer = Vx
+    SetSound { x: usize },                 // Fx18: sound timer = Vx
+    AddI { x: usize },                     // Fx1E: I += Vx
+    LoadFont { x: usize },                 // Fx29: I = font sprite for digit Vx
+    LoadBigFont { x: usize },              // Fx30: SUPER-CHIP I = 10-byte font sprite for digit Vx
+    Bcd { x: usize },                      // Fx33: BCD of Vx at I, I+1, I+2
+    Store { x: usize },                    // Fx55: store V0..Vx at I
+    Load { x: usize },                     // Fx65: read V0..Vx from I
+    SaveFlags { x: usize },                // Fx75: SUPER-CHIP store V0..Vx in the flag registers
+    LoadFlags { x: usize },                // Fx85: SUPER-CHIP read V0..Vx from the flag registers
+    Unknown(u16),                          // anything else
+}
+
+// split an opcode into the instruction it encodes, this doesn't depend on any machine state
+// (quirks are applied when the instruction is executed)
+pub fn decode(opcode: u16) -> Instruction {
+    use Instruction::*;
+
+    let x = ((opcode & 0x0F00) >> 8) as usize;
+    let y = ((opcode & 0x00F0) >> 4) as usize;
+    let n = (opcode & 0x000F) as usize;
+    let kk = (opcode & 0x00FF) as u8;
+    let nnn = opcode & 0x0FFF;
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => Cls,
+            0x00EE => Ret,
+            0x00C0..=0x00CF => ScrollDown(n as u8),
+            0x00D0..=0x00DF => ScrollUp(n as u8),
+            0x00FB => ScrollRight,
+            0x00FC => ScrollLeft,
+            0x00FD => Exit,
+            0x00FE => LowRes,
+            0x00FF => HighRes,
+            _ => Sys(nnn),
+        },
+        0x1000 => Jump(nnn),
+        0x2000 => Call(nnn),
+        0x3000 => SkipEqImm { x, kk },
+        0x4000 => SkipNeImm { x, kk },
+        0x5000 => match n {
+            0x0 => SkipEqReg { x, y },
+            0x2 => SaveRange { x, y },
+            0x3 => LoadRange { x, y },
+            _ => Unknown(opcode),
+        },
+        0x6000 => LoadImm { x, kk },
+        0x7000 => AddImm { x, kk },
+        0x8000 => match n {
+            0x0 => Move { x, y },
+            0x1 => Or { x, y },
+            0x2 => And { x, y },
+            0x3 => Xor { x, y },
+            0x4 => Add { x, y },
+            0x5 => Sub { x, y },
+            0x6 => ShiftRight { x, y },
+            0x7 => SubN { x, y },
+            0xE => ShiftLeft { x, y },
+            _ => Unknown(opcode),
+        },
+        0x9000 => SkipNeReg { x, y },
+        0xA000 => LoadI(nnn),
+        0xB000 => JumpOffset { x, nnn },
+        0xC000 => Random { x, kk },
+        0xD000 => Draw { x, y, n },
+        0xE000 => match kk {
+            0x9E => SkipKey { x },
+            0xA1 => SkipNotKey { x },
+            _ => Unknown(opcode),
+        },
+        0xF000 => match kk {
+            0x00 if x == 0 => LoadLongI,
+            0x01 => SelectPlane(x as u8 & 0x3),
+            0x02 if x == 0 => LoadAudio,
+            0x07 => LoadDelay { x },
+            0x0A => WaitKey { x },
+            0x15 => SetDelay { x },
+            0x18 => SetSound { x },
+            0x1E => AddI { x },
+            0x29 => LoadFont { x },
+            0x30 => LoadBigFont { x },
+            0x33 => Bcd { x },
+            0x55 => Store { x },
+            0x65 => Load { x },
+            0x75 => SaveFlags { x },
+            0x85 => LoadFlags { x },
+            _ => Unknown(opcode),
+        },
+        _ => Unknown(opcode),
+    }
+}
+
+// the COSMAC VIP ran at 1.76MHz with 8 clocks per machine cycle, about this many per 60Hz frame
+pub const VIP_CYCLES_PER_FRAME: u64 = 3668;
+
+// roughly how many COSMAC VIP machine cycles the instruction took, including fetching and decoding it
+// (based on the published VIP interpreter timings, instructions the VIP didn't have cost the same as
+// similar ones that it did). skips are counted as taken and DXYN as drawing within one byte column,
+// the real costs vary a little with the data
+pub fn cycle_cost(inst: &Instruction) -> u32 {
+    use Instruction::*;
+
+    const FETCH: u32 = 40; // every instruction takes this long to fetch and dispatch
+
+    FETCH
+        + match *inst {
+            Sys(_) => 0,
+            Cls => 3024, // clears all 256 bytes of the display
+            Ret => 10,
+            ScrollDown(_) | ScrollUp(_) | ScrollRight | ScrollLeft => 3024,
+            Exit | LowRes | HighRes => 0,
+            Jump(_) => 12,
+            Call(_) => 26,
+            SkipEqImm { .. } | SkipNeImm { .. } => 14,
+            SkipEqReg { .. } | SkipNeReg { .. } => 18,
+            SaveRange { x, y } | LoadRange { x, y } => 14 + 14 * (x.abs_diff(y) as u32 + 1),
+            LoadImm { .. } => 6,
+            AddImm { .. } => 10,
+            Move { .. } | Or { .. } | And { .. } | Xor { .. } => 44,
+            Add { .. } | Sub { .. } | ShiftRight { .. } | SubN { .. } | ShiftLeft { .. } => 44,
+            LoadI(_) => 12,
+            JumpOffset { .. } => 22,
+            Random { .. } => 36,
+            // the VIP drew row by row, 16x16 sprites have 16 rows of two bytes
+            Draw { n: 0, .. } => 68 + 16 * 2 * 46,
+            Draw { n, .. } => 68 + n as u32 * 46,
+            SkipKey { .. } | SkipNotKey { .. } => 18,
+            LoadLongI => 24,
+            SelectPlane(_) | LoadAudio => 10,
+            LoadDelay { .. } | SetDelay { .. } | SetSound { .. } => 10,
+            WaitKey { .. } => 10, // for each time it checks the keypad
+            AddI { .. } => 16,
+            LoadFont { .. } | LoadBigFont { .. } => 20,
+            Bcd { .. } => 84,
+            // one register at a time
+            Store { x } | Load { x } | SaveFlags { x } | LoadFlags { x } => {
+                14 + 14 * (x as u32 + 1)
+            }
+            Unknown(_) => 0,
+        }
+}
+
+// assembly text in the same syntax the assembler reads, e.g. "LD V3, 0x2A" or "DRW V0, V1, 5"
+// (instructions the assembler doesn't know use the usual SUPER-CHIP/XO-CHIP mnemonics)
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            Sys(nnn) => write!(f, "SYS 0x{:03X}", nnn),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollUp(n) => write!(f, "SCU {}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            LowRes => write!(f, "LOW"),
+            HighRes => write!(f, "HIGH"),
+            Jump(nnn) => write!(f, "JP 0x{:03X}", nnn),
+            Call(nnn) => write!(f, "CALL 0x{:03X}", nnn),
+            SkipEqImm { x, kk } => write!(f, "SE V{:X}, 0x{:02X}", x, kk),
+            SkipNeImm { x, kk } => write!(f, "SNE V{:X}, 0x{:02X}", x, kk),
+            SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            SaveRange { x, y } => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            LoadRange { x, y } => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            LoadImm { x, kk } => write!(f, "LD V{:X}, 0x{:02X}", x, kk),
+            AddImm { x, kk } => write!(f, "ADD V{:X}, 0x{:02X}", x, kk),
+            Move { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Add { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            ShiftRight { x, y } if x == y => write!(f, "SHR V{:X}", x),
+            ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SubN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft { x, y } if x == y => write!(f, "SHL V{:X}", x),
+            ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LoadI(nnn) => write!(f, "LD I, 0x{:03X}", nnn),
+            JumpOffset { nnn, .. } => write!(f, "JP V0, 0x{:03X}", nnn),
+            Random { x, kk } => write!(f, "RND V{:X}, 0x{:02X}", x, kk),
+            Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipKey { x } => write!(f, "SKP V{:X}", x),
+            SkipNotKey { x } => write!(f, "SKNP V{:X}", x),
+            LoadLongI => write!(f, "LD I, LONG"),
+            SelectPlane(planes) => write!(f, "PLANE {}", planes),
+            LoadAudio => write!(f, "AUDIO"),
+            LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            AddI { x } => write!(f, "ADD I, V{:X}", x),
+            LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Bcd { x } => write!(f, "LD B, V{:X}", x),
+            Store { x } => write!(f, "LD [I], V{:X}", x),
+            Load { x } => write!(f, "LD V{:X}, [I]", x),
+            SaveFlags { x } => write!(f, "LD R, V{:X}", x),
+            LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+            Unknown(opcode) => write!(f, "DW 0x{:04X}", opcode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_costs_more_than_a_register_load() {
+        assert!(
+            cycle_cost(&Instruction::Draw { x: 0, y: 0, n: 5 })
+                > cycle_cost(&Instruction::LoadImm { x: 0, kk: 1 })
+        );
+    }
+
+    #[test]
+    fn decodes_representative_opcodes() {
+        use Instruction::*;
+
+        for (opcode, expected) in [
+            (0x00E0, Cls),
+            (0x00EE, Ret),
+            (0x0123, Sys(0x123)),
+            (0x1ABC, Jump(0xABC)),
+            (0x2ABC, Call(0xABC)),
+            (0x3A12, SkipEqImm { x: 0xA, kk: 0x12 }),
+            (0x5AB0, SkipEqReg { x: 0xA, y: 0xB }),
+            (0x5AB1, Unknown(0x5AB1)),
+            (0x6A12, LoadImm { x: 0xA, kk: 0x12 }),
+            (0x8AB4, Add { x: 0xA, y: 0xB }),
+            (0x8ABE, ShiftLeft { x: 0xA, y: 0xB }),
+            (0x8AB8, Unknown(0x8AB8)),
+            (0xA123, LoadI(0x123)),
+            (0xB123, JumpOffset { x: 1, nnn: 0x123 }),
+            (
+                0xDAB5,
+                Draw {
+                    x: 0xA,
+                    y: 0xB,
+                    n: 5,
+                },
+            ),
+            (0xEA9E, SkipKey { x: 0xA }),
+            (0xEA00, Unknown(0xEA00)),
+            (0xFA0A, WaitKey { x: 0xA }),
+            (0xFA65, Load { x: 0xA }),
+            (0xF000, LoadLongI),
+            (0xF100, Unknown(0xF100)),
+        ] {
+            assert_eq!(decode(opcode), expected, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn displays_as_assembly() {
+        use alloc::string::ToString;
+
+        for (opcode, text) in [
+            (0x632A, "LD V3, 0x2A"),
+            (0xD015, "DRW V0, V1, 5"),
+            (0x12F8, "JP 0x2F8"),
+            (0x00E0, "CLS"),
+            (0x8AB4, "ADD VA, VB"),
+            (0x8336, "SHR V3"),
+            (0x834E, "SHL V3, V4"),
+            (0xB123, "JP V0, 0x123"),
+            (0xAFFF, "LD I, 0xFFF"),
+            (0xE29E, "SKP V2"),
+            (0xF70A, "LD V7, K"),
+            (0xF565, "LD V5, [I]"),
+            (0xF000, "LD I, LONG"),
+            (0x5FFF, "DW 0x5FFF"),
+        ] {
+            assert_eq!(decode(opcode).to_string(), text, "{:04X}", opcode);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// the emulator core works without std: build it with `cargo build --lib --no-default-features`
+// (this leaves out loading roms from files and png snapshots, and Cxkk uses a fixed-seed rng)
+// `cargo test --lib --no-default-features` checks it still builds and runs the core's tests that way
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+// without std the debug output goes nowhere, the arguments are still type checked
+// (with the log feature nothing prints directly, so the shim is left out)
+#[cfg(all(not(feature = "std"), not(feature = "log")))]
+macro_rules! println {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+// debug output goes through the log crate with the log feature (trace for the per-instruction line,
+// debug for what each instruction does, warn for problems) and is printed without it
+// either way the per-instruction output is only there with the machine's debug flag set
+#[cfg(feature = "log")]
+macro_rules! trace {
+    ($($arg:tt)*) => { log::trace!($($arg)*) };
+}
+#[cfg(feature = "log")]
+macro_rules! debug {
+    ($($arg:tt)*) => { log::debug!($($arg)*) };
+}
+#[cfg(feature = "log")]
+macro_rules! warn {
+    ($($arg:tt)*) => { log::warn!($($arg)*) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! trace {
+    ($($arg:tt)*) => { println!($($arg)*) };
+}
+#[cfg(not(feature = "log"))]
+macro_rules! debug {
+    ($($arg:tt)*) => { println!($($arg)*) };
+}
+#[cfg(not(feature = "log"))]
+macro_rules! warn {
+    ($($arg:tt)*) => { println!("warning: {}", format_args!($($arg)*)) };
+}
+
+pub mod asm;
+pub mod chip8;
+pub mod frontend;
+pub mod instruction;
+pub mod quirks;
+pub mod rng;
+#[cfg(feature = "std")]
+pub mod threaded;
+
+pub use chip8::{CHIP8Builder, CHIP8};
+pub use frontend::Frontend;
+pub use instruction::{cycle_cost, decode, Instruction};
+pub use quirks::{detect_variant, Quirks, Variant};
+pub use rng::Rng;
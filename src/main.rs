@@ -1,128 +1,696 @@
-use minifb::{Key, Window, WindowOptions};
+use chip8::{
+    chip8::{RomError, StepError, TraceHook, UnknownOpcodePolicy},
+    decode,
+    frontend::DEFAULT_INSTRUCTIONS_PER_FRAME,
+    quirks::known_rom,
+    rng::SeededRng,
+    Frontend, Instruction, Variant, CHIP8,
+};
+use minifb::Key;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
-mod chip8;
-use chip8::CHIP8;
+mod demos;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+use demos::{Demo, DEMOS};
+mod minifb_frontend;
+use minifb_frontend::{MinifbFrontend, DEFAULT_FADE_STEP};
+mod recording;
+use recording::{Recorder, Replay};
 
-fn main() {
+// exit codes: 0 when the window is closed normally, 1 when the emulation (or writing a trace or
+// recording) fails, and these for problems with the command line or the rom
+const EXIT_USAGE: u8 = 2; // an unknown option or one missing its value
+const EXIT_UNREADABLE_ROM: u8 = 3; // the rom file doesn't exist or couldn't be read
+const EXIT_ROM_TOO_LARGE: u8 = 4; // the rom doesn't fit in memory
+
+const USAGE: &str = "usage: chip8 [options] [rom]
+
+with no rom the built-in maze demo runs
+  --demo [list|name]      run a built-in rom, or list them
+  --fade                  let erased pixels fade out instead of blinking off
+  --turbo N               how many times faster the cpu runs while Tab is held
+  --dump-state            print the registers and memory when the emulator exits
+  --step                  start paused, Space runs one instruction and F5 continues
+  --mute                  start with the beep off, M toggles it
+  --key-latch             a key pressed at any point during a frame counts for the whole frame
+  --fast-boot             run flat out until the program first draws something
+  --key-repeat-delay N    frames a held key is ignored for after each press
+  --benchmark N           run N instructions headless and report the speed
+  --record path           log the keypad for every frame to a file
+  --replay path           play back a recorded keypad log instead of live input
+  --trace-file path       log every instruction executed to a file
+  --on-unknown-opcode P   halt, skip or error (the default) on an instruction chip8 doesn't have
+  --scale N               draw chip8 pixels N screen pixels big instead of fitting the window";
+
+// one emulated frame, the timers tick at 60Hz
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+// how many emulated frames one loop iteration runs at most to catch up with the clock
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+
+// command line options, the first argument that isn't a flag is the game
+struct Options {
+    game: Option<String>,
+    demo: Option<&'static Demo>, // --demo [name]: run a built-in rom instead of a game
+    list_demos: bool,            // --demo list: show the built-in roms
+    fade: bool,                  // --fade: let erased pixels fade out instead of blinking off
+    turbo: usize,                // --turbo N: how many times faster the cpu runs while Tab is held
+    dump_state: bool, // --dump-state: print the registers and memory when the emulator exits
+    step: bool,       // --step: start paused, Space runs one instruction and F5 continues
+    mute: bool,       // --mute: start with the beep off, M toggles it
+    key_latch: bool, // --key-latch: a key pressed at any point during a frame counts for the whole frame
+    fast_boot: bool, // --fast-boot: run flat out until the program first draws something
+    key_repeat_delay: usize, // --key-repeat-delay N: frames a held key is ignored for after each press
+    benchmark: Option<usize>, // --benchmark N: run N instructions headless and report the speed
+    record: Option<String>,  // --record path: log the keypad for every frame to a file
+    replay: Option<String>,  // --replay path: play back a recorded keypad log instead of live input
+    trace_file: Option<String>, // --trace-file path: log every instruction executed to a file
+    on_unknown_opcode: UnknownOpcodePolicy, // --on-unknown-opcode halt|skip|error
+    scale: Option<usize>, // --scale N: always draw chip8 pixels N screen pixels big instead of fitting the window
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            game: None,
+            demo: None,
+            list_demos: false,
+            fade: false,
+            turbo: 8,
+            dump_state: false,
+            step: false,
+            mute: false,
+            key_latch: false,
+            fast_boot: false,
+            key_repeat_delay: 0,
+            benchmark: None,
+            record: None,
+            replay: None,
+            trace_file: None,
+            on_unknown_opcode: UnknownOpcodePolicy::Error,
+            scale: None,
+        }
+    }
+}
+
+impl Options {
+    // a usage error is the message to show above USAGE
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.peekable();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--fade" => options.fade = true,
+                "--dump-state" => options.dump_state = true,
+                "--step" => options.step = true,
+                "--mute" => options.mute = true,
+                "--key-latch" => options.key_latch = true,
+                "--fast-boot" => options.fast_boot = true,
+                // the name is optional, but a word right after it is always taken as one
+                "--demo" => match args.next_if(|name| !name.starts_with("--")).as_deref() {
+                    Some("list") => options.list_demos = true,
+                    Some(name) => match demos::find(name) {
+                        Some(demo) => options.demo = Some(demo),
+                        None => {
+                            return Err(format!("unknown demo {}, --demo list shows them", name))
+                        }
+                    },
+                    None => options.demo = Some(&DEMOS[0]),
+                },
+                "--turbo" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(factor) => options.turbo = factor,
+                    None => return Err("--turbo expects a number".into()),
+                },
+                "--benchmark" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(instructions) => options.benchmark = Some(instructions),
+                    None => return Err("--benchmark expects a number of instructions".into()),
+                },
+                "--record" => match args.next() {
+                    Some(path) => options.record = Some(path),
+                    None => return Err("--record expects a file".into()),
+                },
+                "--replay" => match args.next() {
+                    Some(path) => options.replay = Some(path),
+                    None => return Err("--replay expects a file".into()),
+                },
+                "--trace-file" => match args.next() {
+                    Some(path) => options.trace_file = Some(path),
+                    None => return Err("--trace-file expects a file".into()),
+                },
+                "--on-unknown-opcode" => match args.next().as_deref() {
+                    Some("halt") => options.on_unknown_opcode = UnknownOpcodePolicy::Halt,
+                    Some("skip") => options.on_unknown_opcode = UnknownOpcodePolicy::Skip,
+                    Some("error") => options.on_unknown_opcode = UnknownOpcodePolicy::Error,
+                    _ => return Err("--on-unknown-opcode expects halt, skip or error".into()),
+                },
+                "--scale" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(scale) if scale > 0 => options.scale = Some(scale),
+                    _ => return Err("--scale expects a number above 0".into()),
+                },
+                "--key-repeat-delay" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(frames) => options.key_repeat_delay = frames,
+                    None => return Err("--key-repeat-delay expects a number of frames".into()),
+                },
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if options.game.is_none() => options.game = Some(arg),
+                _ => eprintln!("ignoring extra argument {}", arg),
+            }
+        }
+
+        if options.demo.is_some() && options.game.is_some() {
+            return Err("--demo runs a built-in rom, it can't be given a rom as well".into());
+        }
+
+        Ok(options)
+    }
+}
+
+fn main() -> ExitCode {
     // initialize the cpu
     let mut chip8 = CHIP8::new();
     chip8.debug = false;
 
-    // get cli game argument
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        println!("first argument should be a game!");
-        return;
+    // get cli game argument, without one there's a demo to look at
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    if options.list_demos {
+        for demo in &DEMOS {
+            println!("{:<12}{}", demo.name, demo.title);
+        }
+        return ExitCode::SUCCESS;
     }
 
-    let game = &args[1];
-    println!("{}", game);
+    // load rom to cpu memory, picking the quirks (and for SUPER-CHIP roms the variant) it needs
+    let (game, variant) = match (options.demo, &options.game) {
+        (None, Some(game)) => {
+            println!("{}", game);
+            match chip8.load_game(game) {
+                Ok(variant) => (Some(game.as_str()), variant),
+                Err(e) => {
+                    eprintln!("couldn't load {}: {:?}", game, e);
+                    return rom_error_code(&e);
+                }
+            }
+        }
+        (demo, _) => {
+            let demo = demo.unwrap_or_else(|| {
+                println!("no game given, running the {} demo", DEMOS[0].name);
+                &DEMOS[0]
+            });
+            println!("{}", demo.title);
+            chip8.load_fonts();
+            if let Err(e) = chip8.load_rom_bytes(demo.rom) {
+                eprintln!("couldn't load the {} demo: {:?}", demo.name, e);
+                return rom_error_code(&e);
+            }
+            chip8.apply_profile_for_loaded_rom();
+            (None, chip8.variant)
+        }
+    };
+    print!("{}", chip8.hexdump(0x200, 128)); // the start of the rom
+    println!("rom hash: {:016X}", chip8.rom_hash());
 
-    // load rom to cpu memory
-    chip8.load_fonts();
-    chip8.load_rom(game);
-    println!("{:x?}", chip8.memory);
+    match known_rom(chip8.rom_hash()) {
+        Some((name, _, _)) => println!("recognised {}, using its quirks", name),
+        None if variant == Variant::SuperChip => {
+            println!("looks like a SUPER-CHIP rom, using its quirks")
+        }
+        None => {}
+    }
 
-    const SCALE_WIDTH: usize = 1024; // scaled 16 times
-    const SCALE_HEIGHT: usize = 512;
+    chip8.on_unknown_opcode = options.on_unknown_opcode;
 
-    const CHIP8_WIDTH: usize = 64; // original
-    const CHIP8_HEIGHT: usize = 32;
+    if let Some(path) = &options.trace_file {
+        match trace_to_file(path) {
+            Ok(hook) => chip8.set_trace_hook(hook),
+            Err(e) => {
+                eprintln!("couldn't create the trace file: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    if let Some(instructions) = options.benchmark {
+        println!("{}", benchmark(&mut chip8, instructions));
+        return ExitCode::SUCCESS;
+    }
+
+    let mut recorder = match options.record.as_deref().map(Recorder::create).transpose() {
+        Ok(recorder) => recorder,
+        Err(e) => {
+            eprintln!("couldn't create the recording: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let replay = match options.replay.as_deref().map(Replay::open).transpose() {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("couldn't read the recording: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // recorded runs must be reproducible, so the random bytes are the same every time too
+    if recorder.is_some() || replay.is_some() {
+        chip8.set_rng(Box::new(SeededRng::new(0)));
+    }
+    let mut frame: u64 = 0;
 
-    const SCALE: usize = 16;
+    const SCALE: usize = 16; // 1024x512 window
 
-    let mut window = Window::new(
+    let mut frontend = MinifbFrontend::new(
         "Test - ESC to exit",
-        SCALE_WIDTH,
-        SCALE_HEIGHT,
-        WindowOptions::default(),
-    )
-    .unwrap_or_else(|e| {
-        panic!("{}", e);
-    });
-
-    // Limit to max ~60 fps update rate
-    window.set_target_fps(240);
-
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        // we use chip8.display to calculate and scale and store in the buffer variable
-        let mut buffer: Vec<u32> = vec![0xFF000000; SCALE_WIDTH * SCALE_HEIGHT];
-
-        for y in 0..CHIP8_HEIGHT {
-            for x in 0..CHIP8_WIDTH {
-                let pixel = chip8.display[y * CHIP8_WIDTH + x];
-                if pixel == 1 {
-                    for dy in 0..SCALE {
-                        for dx in 0..SCALE {
-                            let buffer_index = (y * SCALE + dy) * SCALE_WIDTH + (x * SCALE + dx);
-                            buffer[buffer_index] = 0xFFFFFFFF;
-                        }
-                    }
-                }
-            }
+        options.scale.unwrap_or(SCALE),
+        options.scale.is_some(),
+    );
+    if options.fade {
+        frontend.set_fade(Some(DEFAULT_FADE_STEP));
+    }
+    frontend.set_key_repeat_delay(options.key_repeat_delay);
+    if options.key_latch {
+        frontend.set_key_latch();
+    }
+
+    // with no controller (or no gamepad support on this system) this just never presses anything
+    #[cfg(feature = "gamepad")]
+    let mut gamepad = gamepad::Gamepad::new();
+
+    // F1 shows the registers over the game
+    let mut inspecting = false;
+
+    // the beep is the terminal bell, rung whenever the sound timer starts
+    // muting only silences it, the sound timer itself runs the same either way
+    let mut muted = options.mute;
+    let mut beeping = false;
+
+    let mut stepping = options.step;
+    let mut steps = 0;
+    if stepping {
+        print_upcoming(&chip8);
+    }
+
+    // a failed boot skips the main loop but still ends like any other failure (the state dump, the
+    // trace file and the exit code)
+    let mut status = ExitCode::SUCCESS;
+    let mut booted = true;
+    if options.fast_boot && !stepping {
+        if let Err(e) = fast_boot(&mut chip8) {
+            eprintln!("emulation stopped: {:?}", e);
+            status = ExitCode::FAILURE;
+            booted = false;
         }
+    }
 
-        // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
-        window
-            .update_with_buffer(&buffer, SCALE_WIDTH, SCALE_HEIGHT)
-            .unwrap();
+    // the emulation runs on wall-clock time: every 1/60s that passes runs one emulated frame,
+    // however often the window actually gets presented
+    let mut last_tick = Instant::now();
+    let mut behind = Duration::ZERO;
 
-        chip8.keypad = [false; 16]; // clear keys
-        let keys = window.get_keys();
-        for key in keys {
-            match key {
-                Key::Key1 => {
-                    chip8.keypad[0x1] = true;
-                }
-                Key::Key2 => {
-                    chip8.keypad[0x2] = true;
-                }
-                Key::Key3 => {
-                    chip8.keypad[0x3] = true;
-                }
-                Key::Key4 => {
-                    chip8.keypad[0x4] = true;
-                }
-                Key::Key5 => {
-                    chip8.keypad[0x5] = true;
-                }
-                Key::Key6 => {
-                    chip8.keypad[0x6] = true;
-                }
-                Key::Key7 => {
-                    chip8.keypad[0x7] = true;
-                }
-                Key::Key8 => {
-                    chip8.keypad[0x8] = true;
-                }
-                Key::Key9 => {
-                    chip8.keypad[0x9] = true;
-                }
-                Key::Key0 => {
-                    chip8.keypad[0x0] = true;
-                }
-                Key::A => {
-                    chip8.keypad[0xA] = true;
-                }
-                Key::B => {
-                    chip8.keypad[0xB] = true;
-                }
-                Key::C => {
-                    chip8.keypad[0xC] = true;
+    'running: while booted && !frontend.should_close() {
+        // R reloads the rom from disk, handy while iterating on a homebrew rom
+        if let Some(game) = game.filter(|_| frontend.key_pressed(Key::R)) {
+            reload(&mut chip8, game);
+        }
+
+        if frontend.key_pressed(Key::F1) {
+            inspecting = !inspecting;
+        }
+        if frontend.key_pressed(Key::M) {
+            muted = !muted;
+            frontend.show_message(if muted { "MUTED" } else { "SOUND ON" });
+        }
+        frontend.set_overlay(inspecting.then(|| inspector_text(&chip8)));
+
+        frontend.present(
+            &chip8.display,
+            chip8.display_width(),
+            chip8.display_height(),
+        );
+        chip8.keypad = frontend.poll_keys();
+        #[cfg(feature = "gamepad")]
+        gamepad.poll(&mut chip8.keypad);
+
+        let now = Instant::now();
+        behind += now - last_tick;
+        last_tick = now;
+
+        // single-step mode: keep rendering but only run an instruction when Space is pressed
+        if stepping {
+            behind = Duration::ZERO; // no catching up on the time spent paused
+            if frontend.key_pressed(Key::F5) {
+                println!("continuing");
+                stepping = false;
+            } else if frontend.key_pressed(Key::Space) {
+                if let Err(e) = chip8.cycle() {
+                    eprintln!("emulation stopped: {:?}", e);
+                    status = ExitCode::FAILURE;
+                    break 'running;
                 }
-                Key::D => {
-                    chip8.keypad[0xD] = true;
+
+                // timers tick at the same rate per instruction as they would when running
+                steps += 1;
+                if steps % DEFAULT_INSTRUCTIONS_PER_FRAME == 0 {
+                    chip8.tick_timers();
                 }
-                Key::E => {
-                    chip8.keypad[0xE] = true;
+
+                print_upcoming(&chip8);
+            }
+
+            continue;
+        }
+
+        // holding Tab only speeds up the cpu, the timers keep ticking once per frame (60Hz)
+        // a halted program doesn't run anything, it just keeps its last frame on screen
+        let turbo_ipf = if frontend.key_down(Key::Tab) {
+            DEFAULT_INSTRUCTIONS_PER_FRAME * options.turbo
+        } else {
+            DEFAULT_INSTRUCTIONS_PER_FRAME
+        };
+
+        // a slow present (or the window being dragged) can leave us far behind, only a few frames
+        // are caught up and the rest is dropped rather than fast-forwarding the game
+        let mut caught_up = 0;
+        while behind >= FRAME_TIME {
+            behind -= FRAME_TIME;
+            caught_up += 1;
+            if caught_up > MAX_CATCH_UP_FRAMES {
+                behind = Duration::ZERO;
+                break;
+            }
+
+            // frames are only counted while the program runs, so recordings line up with the emulation
+            // a replay uses the recorded ipf too, once it runs out nothing is held
+            let ipf = match &replay {
+                Some(replay) => {
+                    let (keys, recorded_ipf) =
+                        replay.frame(frame).unwrap_or(([false; 16], turbo_ipf));
+                    chip8.keypad = keys;
+                    recorded_ipf
                 }
-                Key::F => {
-                    chip8.keypad[0xF] = true;
+                None => turbo_ipf,
+            };
+            if let Some(recorder) = &mut recorder {
+                if let Err(e) = recorder.record(frame, &chip8.keypad, ipf) {
+                    eprintln!("couldn't write the recording: {}", e);
+                    status = ExitCode::FAILURE;
+                    break 'running;
                 }
-                _ => {}
             }
+            frame += 1;
+
+            if let Err(e) = chip8.run_frame(ipf) {
+                eprintln!("emulation stopped: {:?}", e);
+                status = ExitCode::FAILURE;
+                break 'running;
+            }
+
+            if chip8.is_beeping() && !beeping && !muted {
+                print!("\x07");
+                let _ = io::stdout().flush();
+            }
+            beeping = chip8.is_beeping();
+        }
+    }
+
+    if options.dump_state {
+        print!("{}", chip8.dump_state());
+    }
+
+    // closes the trace file, with everything written to it
+    chip8.clear_trace_hook();
+    status
+}
+
+// a rom that can't be read gets a different exit code from one that doesn't fit in memory
+fn rom_error_code(e: &RomError) -> ExitCode {
+    ExitCode::from(match e {
+        RomError::Io(_) => EXIT_UNREADABLE_ROM,
+        RomError::TooLarge(_) | RomError::OutOfBounds(_) => EXIT_ROM_TOO_LARGE,
+    })
+}
+
+// skip a rom's setup by running it unthrottled until it first draws, waits for a key or halts
+// it still runs in whole frames of DEFAULT_INSTRUCTIONS_PER_FRAME with a timer tick after each, so
+// delay loops take the same number of instructions as they would at normal speed
+// the display wait quirk doesn't come into it, the first draw ends the burst anyway
+fn fast_boot(chip8: &mut CHIP8) -> Result<(), StepError> {
+    // a program that never draws gets ten emulated seconds before we give up and run it normally
+    const MAX_BOOT_FRAMES: usize = 600;
+
+    for _ in 0..MAX_BOOT_FRAMES {
+        let drew = chip8.run_until_draw(DEFAULT_INSTRUCTIONS_PER_FRAME)?;
+        chip8.tick_timers();
+
+        let waiting = matches!(
+            chip8.peek_opcode().map(decode),
+            Some(Instruction::WaitKey { .. })
+        );
+        if drew || waiting || chip8.is_halted() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// a trace hook writing one line per instruction, e.g. "PC:0200 OP:6005  LD V0, 0x05"
+// the format doesn't change so traces can be diffed against each other and other emulators' logs
+fn trace_to_file(path: &str) -> io::Result<TraceHook> {
+    // flushed every so often so a trace is mostly there even if the emulator crashes
+    const FLUSH_INTERVAL: usize = 4096;
+
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut lines = 0;
+    let mut failed = false;
+
+    Ok(Box::new(move |pc, opcode, inst| {
+        if failed {
+            return;
+        }
+
+        let mut result = writeln!(out, "PC:{:04X} OP:{:04X}  {}", pc, opcode, inst);
+        lines += 1;
+        if lines % FLUSH_INTERVAL == 0 {
+            result = result.and_then(|()| out.flush());
+        }
+
+        // one warning is enough, the trace is incomplete from here on either way
+        if let Err(e) = result {
+            eprintln!("warning: couldn't write the trace: {}", e);
+            failed = true;
+        }
+    }))
+}
+
+// what a benchmark run measured
+struct Benchmark {
+    executed: usize,
+    elapsed: Duration,
+}
+
+impl Benchmark {
+    fn mips(&self) -> f64 {
+        self.executed as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON) / 1_000_000.0
+    }
+}
+
+impl std::fmt::Display for Benchmark {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "ran {} instructions in {:.3?}: {:.2} MIPS, {:.1} ns per instruction",
+            self.executed,
+            self.elapsed,
+            self.mips(),
+            self.elapsed.as_nanos() as f64 / self.executed.max(1) as f64
+        )
+    }
+}
+
+// run the rom without a window as fast as possible and measure how quick the interpreter is
+// the rng is seeded so runs of the same rom are comparable
+fn benchmark(chip8: &mut CHIP8, instructions: usize) -> Benchmark {
+    chip8.set_rng(Box::new(SeededRng::new(0)));
+
+    let start = Instant::now();
+    let mut executed = 0;
+    while executed < instructions && !chip8.is_halted() {
+        if let Err(e) = chip8.cycle() {
+            eprintln!("emulation stopped: {:?}", e);
+            break;
         }
+        executed += 1;
+
+        // keep the timers running at their usual rate so delay loops still finish
+        if executed % DEFAULT_INSTRUCTIONS_PER_FRAME == 0 {
+            chip8.tick_timers();
+        }
+    }
+    let elapsed = start.elapsed();
+
+    if chip8.is_halted() && executed < instructions {
+        println!("the program halted after {} instructions", executed);
+    }
+
+    Benchmark { executed, elapsed }
+}
+
+// the F1 overlay: PC and I, the V registers four to a line, the timers and the innermost few return
+// addresses (the most recent first)
+fn inspector_text(chip8: &CHIP8) -> String {
+    use std::fmt::Write;
+
+    let mut text = String::new();
+    let _ = writeln!(text, "PC {:04X}", chip8.program_counter());
+    let _ = writeln!(text, "I  {:04X}", chip8.index_register());
+    for first in (0..16).step_by(4) {
+        let _ = write!(text, "V{:X} ", first);
+        for idx in first..first + 4 {
+            let _ = write!(text, " {:02X}", chip8.get_register(idx).unwrap_or(0));
+        }
+        text.push('\n');
+    }
+    let _ = writeln!(
+        text,
+        "DT {:02X} ST {:02X}",
+        chip8.delay_timer(),
+        chip8.sound_timer()
+    );
+    let _ = write!(text, "SP {:X} ", chip8.stack_depth());
+    for addr in chip8.call_stack().iter().rev().take(4) {
+        let _ = write!(text, " {:04X}", addr);
+    }
+
+    text
+}
+
+// show the instruction that will run on the next step
+fn print_upcoming(chip8: &CHIP8) {
+    let pc = chip8.program_counter();
+    match chip8.peek_opcode() {
+        Some(opcode) => println!("next: {:04X}: {:04X} {}", pc, opcode, decode(opcode)),
+        None => println!("next: {:04X}: out of memory", pc),
+    }
+}
+
+// reset the machine and load the rom again, keeping the current state if the file can't be read
+// or doesn't fit (it may be missing or half written for a moment while it's being rebuilt)
+fn reload(chip8: &mut CHIP8, game: &str) {
+    match chip8.load_game(game) {
+        Ok(_) => println!("reloaded {}", game),
+        Err(e) => println!("warning: couldn't reload {}: {:?}", game, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn on_unknown_opcode_option() {
+        assert_eq!(
+            parse(&["game.ch8"]).unwrap().on_unknown_opcode,
+            UnknownOpcodePolicy::Error
+        );
+        assert_eq!(
+            parse(&["--on-unknown-opcode", "skip", "game.ch8"])
+                .unwrap()
+                .on_unknown_opcode,
+            UnknownOpcodePolicy::Skip
+        );
+        assert_eq!(
+            parse(&["--on-unknown-opcode", "halt"])
+                .unwrap()
+                .on_unknown_opcode,
+            UnknownOpcodePolicy::Halt
+        );
+        assert!(parse(&["--on-unknown-opcode", "ignore"]).is_err());
+        assert!(parse(&["--on-unknown-opcode"]).is_err());
+    }
+
+    #[test]
+    fn demo_option() {
+        let options = parse(&["--demo"]).unwrap();
+        assert_eq!(options.demo.unwrap().name, DEMOS[0].name);
+        assert!(!options.list_demos);
+
+        let options = parse(&["--demo", "list"]).unwrap();
+        assert!(options.list_demos);
+        assert!(options.demo.is_none());
+
+        let options = parse(&["--demo", "maze", "--mute"]).unwrap();
+        assert_eq!(options.demo.unwrap().name, "maze");
+        assert!(options.mute);
+        assert!(options.game.is_none());
+
+        assert!(parse(&["--demo", "bogus"]).is_err());
+        assert!(parse(&["--demo", "--fade", "game.ch8"]).is_err());
+        assert!(parse(&["game.ch8", "--demo", "maze"]).is_err());
+    }
+
+    #[test]
+    fn benchmark_reports_its_speed() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // ADD V0, 1; JP 200
+
+        let report = benchmark(&mut chip8, 10_000);
+        assert_eq!(report.executed, 10_000);
+        assert!(report.mips() > 0.0);
+        assert!(report.to_string().starts_with("ran 10000 instructions in "));
+    }
+
+    #[test]
+    fn trace_file_has_a_line_per_instruction() {
+        let path = std::env::temp_dir().join(format!("chip8-trace-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut chip8 = CHIP8::new();
+        chip8.set_trace_hook(trace_to_file(path).unwrap());
+        chip8
+            .load_rom_bytes(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x02])
+            .unwrap(); // LD V0, 5; ADD V0, 1; JP 202
+        for _ in 0..7 {
+            chip8.cycle().unwrap();
+        }
+        chip8.clear_trace_hook(); // closes the file
+
+        let trace = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(trace.lines().count(), 7);
+        assert_eq!(trace.lines().next(), Some("PC:0200 OP:6005  LD V0, 0x05"));
+    }
+
+    #[test]
+    fn usage_and_rom_errors_have_their_own_exit_codes() {
+        assert!(parse(&["--frobnicate", "game.ch8"]).is_err());
+        assert!(parse(&["--scale"]).is_err());
+        assert!(parse(&[]).unwrap().game.is_none()); // not an error, the demo runs instead
 
-        chip8.cycle();
+        let mut chip8 = CHIP8::new();
+        let missing = chip8
+            .load_game("roms/there is no such rom.ch8")
+            .unwrap_err();
+        assert_eq!(
+            rom_error_code(&missing),
+            ExitCode::from(EXIT_UNREADABLE_ROM)
+        );
+        let too_large = chip8.load_rom_bytes(&[0; 5000]).unwrap_err();
+        assert_eq!(
+            rom_error_code(&too_large),
+            ExitCode::from(EXIT_ROM_TOO_LARGE)
+        );
     }
 }
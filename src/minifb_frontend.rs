@@ -0,0 +1,362 @@
+use chip8::{
+    chip8::{CHIP8_HEIGHT, CHIP8_WIDTH, FONT_SET},
+    frontend::{scale_into_palette, scale_into_with, DEFAULT_PALETTE},
+    Frontend,
+};
+use minifb::{InputCallback, Key, KeyRepeat, Window, WindowOptions};
+use std::cell::Cell;
+use std::rc::Rc;
+
+const FOREGROUND: u32 = DEFAULT_PALETTE[1];
+const BACKGROUND: u32 = DEFAULT_PALETTE[0];
+const OVERLAY_BACKGROUND: u32 = 0xFF203040; // distinct from anything the display can show
+
+// how long show_message text stays up, in frames
+const MESSAGE_FRAMES: usize = 60;
+
+// letters the overlay and messages need that aren't hex digits, in the same 4x5 style as FONT_SET
+const OVERLAY_LETTERS: [(char, [u8; 5]); 9] = [
+    ('I', [0xE0, 0x40, 0x40, 0x40, 0xE0]),
+    ('M', [0x90, 0xF0, 0x90, 0x90, 0x90]),
+    ('N', [0x90, 0xD0, 0xB0, 0x90, 0x90]),
+    ('O', [0x60, 0x90, 0x90, 0x90, 0x60]),
+    ('P', [0xE0, 0x90, 0xE0, 0x80, 0x80]),
+    ('S', [0x70, 0x80, 0x60, 0x10, 0xE0]),
+    ('T', [0xF0, 0x40, 0x40, 0x40, 0x40]),
+    ('U', [0x90, 0x90, 0x90, 0x90, 0x60]),
+    ('V', [0x90, 0x90, 0x90, 0x60, 0x60]),
+];
+
+// the 4x5 glyph for a character, None draws nothing (spaces and anything unknown)
+fn glyph(c: char) -> Option<[u8; 5]> {
+    match c.to_digit(16) {
+        Some(digit) => FONT_SET[digit as usize * 5..][..5].try_into().ok(),
+        None => OVERLAY_LETTERS
+            .iter()
+            .find(|(letter, _)| *letter == c)
+            .map(|(_, rows)| *rows),
+    }
+}
+
+// how much a faded pixel loses per frame by default (out of 255)
+pub const DEFAULT_FADE_STEP: u8 = 48;
+
+pub struct MinifbFrontend {
+    window: Window,
+    buffer: Vec<u32>,
+    scaled: Vec<u32>, // the display scaled up, before it's copied into the middle of the buffer
+    // the buffer covers the whole window, the display is scaled up and centered in it
+    width: usize,
+    height: usize,
+    fixed_scale: Option<usize>, // None picks the biggest scale that fits the window every frame
+
+    // phosphor effect: per-pixel intensity that decays by `fade` every frame once the pixel is off
+    fade: Option<u8>,
+    intensity: Vec<u8>,
+
+    // debounce: a held key only reads as pressed once every repeat_delay + 1 frames (0 turns it off)
+    repeat_delay: usize,
+    cooldown: [usize; 16], // frames each key stays suppressed for
+
+    // keys pressed since the last poll_keys, Some once set_key_latch turned latching on
+    latched: Option<Rc<Cell<u16>>>,
+
+    overlay: Option<String>, // lines of text drawn over the top left of the window
+    message: Option<(String, usize)>, // text in the bottom left and the frames it stays up for
+}
+
+// the keypad key a keyboard key is mapped to: 0-9 and A-F
+fn keypad_index(key: Key) -> Option<usize> {
+    match key {
+        Key::Key0 => Some(0x0),
+        Key::Key1 => Some(0x1),
+        Key::Key2 => Some(0x2),
+        Key::Key3 => Some(0x3),
+        Key::Key4 => Some(0x4),
+        Key::Key5 => Some(0x5),
+        Key::Key6 => Some(0x6),
+        Key::Key7 => Some(0x7),
+        Key::Key8 => Some(0x8),
+        Key::Key9 => Some(0x9),
+        Key::A => Some(0xA),
+        Key::B => Some(0xB),
+        Key::C => Some(0xC),
+        Key::D => Some(0xD),
+        Key::E => Some(0xE),
+        Key::F => Some(0xF),
+        _ => None,
+    }
+}
+
+// sees every key event as the window gets it, so taps that start and end between two frames
+// aren't lost. the keypad keys pressed since the last poll are collected as a bitmask
+struct KeyLatch(Rc<Cell<u16>>);
+
+impl InputCallback for KeyLatch {
+    fn add_char(&mut self, _uni_char: u32) {}
+
+    fn set_key_state(&mut self, key: Key, state: bool) {
+        if let Some(index) = keypad_index(key).filter(|_| state) {
+            self.0.set(self.0.get() | 1 << index);
+        }
+    }
+}
+
+// blend between the background and the foreground color, intensity 0 is background and 255 foreground
+fn fade_color(intensity: u8) -> u32 {
+    let channel = |shift: u32| {
+        let bg = (BACKGROUND >> shift) & 0xFF;
+        let fg = (FOREGROUND >> shift) & 0xFF;
+        let value = (bg * (255 - intensity as u32) + fg * intensity as u32) / 255;
+        value << shift
+    };
+
+    0xFF000000 | channel(16) | channel(8) | channel(0)
+}
+
+impl MinifbFrontend {
+    // open a resizable window big enough to show a 64x32 display scaled `scale` times
+    // with a fixed scale the display stays that size whatever the window does, otherwise it's
+    // rescaled to fit whenever the window is resized
+    pub fn new(title: &str, scale: usize, fixed_scale: bool) -> Self {
+        let width = CHIP8_WIDTH * scale;
+        let height = CHIP8_HEIGHT * scale;
+
+        let options = WindowOptions {
+            resize: true,
+            ..WindowOptions::default()
+        };
+        let mut window = Window::new(title, width, height, options).unwrap_or_else(|e| {
+            panic!("{}", e);
+        });
+
+        // Limit to max ~60 fps update rate, the emulation speed follows the clock rather than this
+        window.set_target_fps(60);
+
+        Self {
+            window,
+            buffer: vec![BACKGROUND; width * height],
+            scaled: Vec::new(),
+            width,
+            height,
+            fixed_scale: fixed_scale.then_some(scale),
+
+            fade: None,
+            intensity: vec![0; CHIP8_WIDTH * CHIP8_HEIGHT],
+
+            repeat_delay: 0,
+            cooldown: [0; 16],
+
+            latched: None,
+
+            overlay: None,
+            message: None,
+        }
+    }
+
+    pub fn key_down(&self, key: Key) -> bool {
+        self.window.is_key_down(key)
+    }
+
+    // true only on the frame the key went down
+    pub fn key_pressed(&self, key: Key) -> bool {
+        self.window.is_key_pressed(key, KeyRepeat::No)
+    }
+
+    // enable the fade effect with the given per-frame decay, None turns it off
+    pub fn set_fade(&mut self, step: Option<u8>) {
+        self.fade = step;
+    }
+
+    // after a key reads as pressed, ignore it for this many frames while it stays held
+    pub fn set_key_repeat_delay(&mut self, frames: usize) {
+        self.repeat_delay = frames;
+    }
+
+    // latch key presses: any key pressed at some point since the last frame reads as held for
+    // the whole next frame, even if it was already let go. fast games catch the shortest taps this
+    // way, but a tap also holds a key for a full frame where sampling once a frame (the default)
+    // would have missed it. it can't be turned off again
+    pub fn set_key_latch(&mut self) {
+        if self.latched.is_none() {
+            let latched = Rc::new(Cell::new(0));
+            self.window
+                .set_input_callback(Box::new(KeyLatch(Rc::clone(&latched))));
+            self.latched = Some(latched);
+        }
+    }
+
+    // text to show over the display from the next frame on, None hides it
+    // only hex digits, spaces and the letters in OVERLAY_LETTERS can be drawn
+    pub fn set_overlay(&mut self, text: Option<String>) {
+        self.overlay = text;
+    }
+
+    // show a line of text in the bottom left for about a second (with the same limits as set_overlay)
+    pub fn show_message(&mut self, text: &str) {
+        self.message = Some((text.to_string(), MESSAGE_FRAMES));
+    }
+
+    // text goes into the window buffer after the display, so the emulated pixels (and their fade
+    // state) are never touched
+    fn draw_text_boxes(&mut self, pixel: usize) {
+        if let Some(text) = self.overlay.take() {
+            self.draw_text(&text, false, pixel);
+            self.overlay = Some(text);
+        }
+
+        if let Some((text, frames)) = self.message.take() {
+            self.draw_text(&text, true, pixel);
+            self.message = (frames > 1).then_some((text, frames - 1));
+        }
+    }
+
+    // draw text on a box in the top left corner of the window, or the bottom left one
+    fn draw_text(&mut self, text: &str, bottom: bool, pixel: usize) {
+        // every glyph is 4x5 with a pixel of space after it, plus a pixel of border around the text
+        let columns = text.lines().map(|line| line.len()).max().unwrap_or(0);
+        let box_width = ((columns * 5 + 1) * pixel).min(self.width);
+        let box_height = ((text.lines().count() * 6 + 1) * pixel).min(self.height);
+        let box_top = if bottom { self.height - box_height } else { 0 };
+        for y in box_top..box_top + box_height {
+            self.buffer[y * self.width..][..box_width].fill(OVERLAY_BACKGROUND);
+        }
+
+        for (row, line) in text.lines().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let Some(rows) = glyph(c) else {
+                    continue;
+                };
+
+                for (gy, bits) in rows.iter().enumerate() {
+                    for gx in 0..4 {
+                        if bits & (0x80 >> gx) == 0 {
+                            continue;
+                        }
+
+                        let left = (1 + column * 5 + gx) * pixel;
+                        let top = box_top + (1 + row * 6 + gy) * pixel;
+                        for y in top..(top + pixel).min(box_top + box_height) {
+                            for x in left..(left + pixel).min(box_width) {
+                                self.buffer[y * self.width + x] = FOREGROUND;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Frontend for MinifbFrontend {
+    fn present(&mut self, display: &[u8], width: usize, height: usize) {
+        // follow the window size, the buffer always covers all of it so nothing gets stretched
+        let (window_width, window_height) = self.window.get_size();
+        self.width = window_width.max(1);
+        self.height = window_height.max(1);
+        self.buffer.resize(self.width * self.height, BACKGROUND);
+
+        // the biggest whole scale that fits keeps the pixels square, what's left over becomes
+        // black bars around the display
+        let scale = self
+            .fixed_scale
+            .unwrap_or((self.width / width).min(self.height / height))
+            .max(1);
+        let left = self.width.saturating_sub(width * scale) / 2;
+        let top = self.height.saturating_sub(height * scale) / 2;
+
+        // the display is scaled on its own and then copied into the buffer, which is mostly
+        // background around it
+        self.scaled
+            .resize(width * scale * height * scale, BACKGROUND);
+        match self.fade {
+            Some(step) => {
+                // lit pixels jump to full brightness, unlit ones fade out over a few frames
+                self.intensity.resize(width * height, 0);
+                for (intensity, &pixel) in self.intensity.iter_mut().zip(display) {
+                    *intensity = if pixel != 0 {
+                        255
+                    } else {
+                        intensity.saturating_sub(step)
+                    };
+                }
+
+                scale_into_with(
+                    &self.intensity,
+                    width,
+                    height,
+                    &mut self.scaled,
+                    scale,
+                    fade_color,
+                );
+            }
+            None => scale_into_palette(
+                display,
+                width,
+                height,
+                &mut self.scaled,
+                scale,
+                &DEFAULT_PALETTE,
+            ),
+        }
+
+        // a fixed scale can be too big for the window, the display is cut off then
+        self.buffer.fill(BACKGROUND);
+        let line = width * scale;
+        let visible = line.min(self.width - left);
+        for (row, pixels) in self.scaled.chunks(line).enumerate() {
+            let buffer_y = top + row;
+            if buffer_y >= self.height {
+                break;
+            }
+
+            self.buffer[buffer_y * self.width + left..][..visible]
+                .copy_from_slice(&pixels[..visible]);
+        }
+
+        // the text is about a quarter the size of the chip8 pixels so it covers less of the game
+        self.draw_text_boxes((scale / 4).max(1));
+
+        // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
+        self.window
+            .update_with_buffer(&self.buffer, self.width, self.height)
+            .unwrap();
+    }
+
+    fn poll_keys(&mut self) -> [bool; 16] {
+        let mut keypad = [false; 16];
+
+        for key in self.window.get_keys() {
+            if let Some(index) = keypad_index(key) {
+                keypad[index] = true;
+            }
+        }
+
+        // add presses that came and went within the frame
+        if let Some(latched) = &self.latched {
+            let mask = latched.replace(0);
+            for (index, pressed) in keypad.iter_mut().enumerate() {
+                *pressed |= mask & (1 << index) != 0;
+            }
+        }
+
+        if self.repeat_delay > 0 {
+            for (pressed, cooldown) in keypad.iter_mut().zip(self.cooldown.iter_mut()) {
+                if !*pressed {
+                    *cooldown = 0; // released, the next press goes through straight away
+                } else if *cooldown > 0 {
+                    *cooldown -= 1;
+                    *pressed = false;
+                } else {
+                    *cooldown = self.repeat_delay;
+                }
+            }
+        }
+
+        keypad
+    }
+
+    fn should_close(&self) -> bool {
+        !self.window.is_open() || self.window.is_key_down(Key::Escape)
+    }
+}
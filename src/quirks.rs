@@ -0,0 +1,275 @@
+use alloc::vec;
+
+use crate::instruction::Instruction;
+
+// behaviours that differ between chip8 interpreters, the defaults match what this emulator has always done
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quirks {
+    // 8xy6/8xyE shift Vy into Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    // Fx55/Fx65 leave I pointing past the last register
+    pub load_store_increments_i: bool,
+    // 8xy1/8xy2/8xy3 clear VF
+    pub vf_reset: bool,
+    // Bnnn jumps to xnn + Vx instead of nnn + V0
+    pub jump_uses_vx: bool,
+    // DXYN wraps sprite pixels past the right edge around to the left one instead of dropping them
+    // (the starting position always wraps, this is only about the pixels that overflow it)
+    pub wrap_x: bool,
+    // the same for pixels past the bottom edge, some interpreters wrap columns but clip rows
+    pub wrap_y: bool,
+    // the buzzer sounds while the sound timer is above this, the VIP's buzzer can't be heard at 1
+    pub sound_threshold: u8,
+    // DXYN waits for the next 60Hz frame (the VIP drew during the vertical blank), so at most one
+    // sprite is drawn per frame
+    pub display_wait: bool,
+    // Fx1E sets VF to 1 when I goes past 0x0FFF (and 0 otherwise), like the Amiga interpreter
+    // Spacefight 2091! relies on this
+    pub fx1e_sets_vf: bool,
+    // I only has 12 bits, so Fx1E and Fx55/Fx65 wrap it at 0x1000 (XO-CHIP keeps all 16 bits for
+    // its 64KB of memory)
+    pub twelve_bit_i: bool,
+    // 00FE/00FF clear the display when they change the resolution (Octo, later SUPER-CHIP
+    // versions) instead of keeping what's there at the same coordinates (SUPER-CHIP 1.1)
+    pub resolution_switch_clears: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            vf_reset: false,
+            jump_uses_vx: false,
+            wrap_x: true,
+            wrap_y: true,
+            sound_threshold: 0,
+            display_wait: false,
+            fx1e_sets_vf: false,
+            twelve_bit_i: false,
+            resolution_switch_clears: true,
+        }
+    }
+}
+
+impl Quirks {
+    // the original COSMAC VIP interpreter, what most conformance test roms expect
+    pub fn cosmac_vip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            vf_reset: true,
+            jump_uses_vx: false,
+            wrap_x: false,
+            wrap_y: false,
+            sound_threshold: 1,
+            display_wait: true,
+            fx1e_sets_vf: false,
+            twelve_bit_i: true,
+            resolution_switch_clears: false,
+        }
+    }
+
+    // SUPER-CHIP 1.1 on the HP48 calculators
+    pub fn super_chip() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            vf_reset: false,
+            jump_uses_vx: true,
+            wrap_x: false,
+            wrap_y: false,
+            sound_threshold: 0,
+            display_wait: false,
+            fx1e_sets_vf: false,
+            twelve_bit_i: true,
+            resolution_switch_clears: false,
+        }
+    }
+
+    // XO-CHIP as Octo runs it: the VIP's shift and load/store behaviour, but sprites wrap
+    pub fn xo_chip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            vf_reset: false,
+            jump_uses_vx: false,
+            wrap_x: true,
+            wrap_y: true,
+            sound_threshold: 0,
+            display_wait: false,
+            fx1e_sets_vf: false,
+            twelve_bit_i: false,
+            resolution_switch_clears: true,
+        }
+    }
+
+    // what every value written to I by an instruction is masked with
+    pub fn i_register_mask(&self) -> u16 {
+        if self.twelve_bit_i {
+            0x0FFF
+        } else {
+            0xFFFF
+        }
+    }
+}
+
+// a rom that needs a particular profile to play properly
+struct KnownRom {
+    hash: u64, // CHIP8::rom_hash of the rom
+    name: &'static str,
+    variant: Variant, // the instructions it's allowed, SUPER-CHIP games get its high res window too
+    profile: fn() -> Quirks,
+}
+
+const KNOWN_ROMS: [KnownRom; 5] = [
+    // clipping keeps the buildings from wrapping onto the top rows, display wait keeps the plane
+    // slow enough to land
+    KnownRom {
+        hash: 0x29BCAB9B664D212B,
+        name: "BLITZ",
+        variant: Variant::Chip8,
+        profile: Quirks::cosmac_vip,
+    },
+    KnownRom {
+        hash: 0xE59FD57FA44ECB40,
+        name: "15PUZZLE",
+        variant: Variant::Chip8,
+        profile: Quirks::cosmac_vip,
+    },
+    // written for CHIP-48/SUPER-CHIP: shifts in place and Fx55/Fx65 don't move I
+    KnownRom {
+        hash: 0x0FD332D0BC68C9F2,
+        name: "BLINKY",
+        variant: Variant::SuperChip,
+        profile: Quirks::super_chip,
+    },
+    KnownRom {
+        hash: 0x8E547EBB12C026B4,
+        name: "INVADERS",
+        variant: Variant::SuperChip,
+        profile: Quirks::super_chip,
+    },
+    KnownRom {
+        hash: 0x618A84F06FE32861,
+        name: "Space Invaders [David Winter]",
+        variant: Variant::SuperChip,
+        profile: Quirks::super_chip,
+    },
+];
+
+// the name, variant and quirks of a known rom
+pub fn known_rom(hash: u64) -> Option<(&'static str, Variant, Quirks)> {
+    KNOWN_ROMS
+        .iter()
+        .find(|rom| rom.hash == hash)
+        .map(|rom| (rom.name, rom.variant, (rom.profile)()))
+}
+
+// the interpreter family a rom was written for, each one adds instructions to the one before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Variant {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Variant {
+    // the quirks that go with the variant
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Variant::Chip8 => Quirks::cosmac_vip(),
+            Variant::SuperChip => Quirks::super_chip(),
+            Variant::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    // whether programs for this variant can use the instruction
+    pub fn supports(self, inst: &Instruction) -> bool {
+        use Instruction::*;
+
+        let needs = match inst {
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit => Variant::SuperChip,
+            LowRes | HighRes => Variant::SuperChip,
+            Draw { n: 0, .. } => Variant::SuperChip, // the 16x16 sprite, a chip8 draws nothing
+            LoadBigFont { .. } | SaveFlags { .. } | LoadFlags { .. } => Variant::SuperChip,
+            ScrollUp(_) | SaveRange { .. } | LoadRange { .. } => Variant::XoChip,
+            LoadLongI | SelectPlane(_) | LoadAudio => Variant::XoChip,
+            _ => Variant::Chip8,
+        };
+
+        self as u8 >= needs as u8
+    }
+}
+
+// guess the variant from the opcodes in a rom loaded at 0x200: any SUPER-CHIP only instruction means SuperChip
+// this is only a heuristic, the code is followed from the entry point through jumps, calls and skips
+// so most sprite data is never looked at, but computed jumps (Bnnn) can't be followed and data
+// placed right after code can still be mistaken for an instruction
+pub fn detect_variant(rom: &[u8]) -> Variant {
+    const ORIGIN: usize = 0x200;
+
+    let mut visited = vec![false; rom.len()];
+    let mut pending = vec![ORIGIN];
+
+    while let Some(addr) = pending.pop() {
+        let Some(offset) = addr.checked_sub(ORIGIN) else {
+            continue;
+        };
+        if offset + 1 >= rom.len() || visited[offset] {
+            continue;
+        }
+        visited[offset] = true;
+
+        let opcode = ((rom[offset] as u16) << 8) | rom[offset + 1] as u16;
+        let nnn = (opcode & 0x0FFF) as usize;
+
+        let super_chip = match opcode & 0xF000 {
+            // 00CN scroll down, 00FB/00FC scroll right/left, 00FD exit, 00FE/00FF low/high res
+            0x0000 => matches!(opcode, 0x00C1..=0x00CF | 0x00FB..=0x00FF),
+            0xD000 => opcode & 0x000F == 0, // Dxy0 16x16 sprite
+            0xF000 => matches!(opcode & 0x00FF, 0x30 | 0x75 | 0x85), // big font, flag registers
+            _ => false,
+        };
+        if super_chip {
+            return Variant::SuperChip;
+        }
+
+        match opcode & 0xF000 {
+            _ if opcode == 0x00EE => {} // the caller carries on after the call
+            0x1000 => pending.push(nnn),
+            0x2000 => pending.extend([nnn, addr + 2]),
+            0xB000 => {} // computed jump, nowhere to follow
+            // skips can land on either of the next two instructions
+            0x3000 | 0x4000 | 0x5000 | 0x9000 => pending.extend([addr + 2, addr + 4]),
+            0xE000 => pending.extend([addr + 2, addr + 4]),
+            _ => pending.push(addr + 2),
+        }
+    }
+
+    Variant::Chip8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_variant_follows_the_code() {
+        // 00FF high res on the path from the entry point
+        assert_eq!(
+            detect_variant(&[0x00, 0xFF, 0x12, 0x02]),
+            Variant::SuperChip
+        );
+        // reached through a skip
+        assert_eq!(
+            detect_variant(&[0x30, 0x00, 0x12, 0x06, 0x00, 0xFF, 0x12, 0x06]),
+            Variant::SuperChip
+        );
+        // the same bytes as sprite data past an endless loop don't count
+        assert_eq!(detect_variant(&[0x12, 0x00, 0x00, 0xFF]), Variant::Chip8);
+        assert_eq!(detect_variant(&[]), Variant::Chip8);
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+// input recordings are text, one line per frame: the frame number, the keypad as a hex bitmask
+// (bit n set means key n is held) and how many instructions ran that frame (turbo changes it)
+// e.g. "120 0020 10" is key 5 held on frame 120
+
+fn keypad_mask(keypad: &[bool; 16]) -> u16 {
+    keypad
+        .iter()
+        .enumerate()
+        .fold(0, |mask, (key, &held)| mask | ((held as u16) << key))
+}
+
+fn keypad_from_mask(mask: u16) -> [bool; 16] {
+    core::array::from_fn(|key| mask & (1 << key) != 0)
+}
+
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn record(&mut self, frame: u64, keypad: &[bool; 16], ipf: usize) -> io::Result<()> {
+        writeln!(self.out, "{} {:04X} {}", frame, keypad_mask(keypad), ipf)
+    }
+}
+
+pub struct Replay {
+    frames: Vec<(u64, u16, usize)>, // frame, keys and ipf, sorted by frame number
+}
+
+impl Replay {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let mut frames = Vec::new();
+
+        for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            let mut fields = line.split_whitespace();
+            let mut parsed = || {
+                let frame = fields.next()?.parse().ok()?;
+                let mask = u16::from_str_radix(fields.next()?, 16).ok()?;
+                let ipf = fields.next()?.parse().ok()?;
+                fields.next().is_none().then_some((frame, mask, ipf))
+            };
+
+            match parsed() {
+                Some(entry) => frames.push(entry),
+                None if line.trim().is_empty() => {}
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "line {}: expected \"frame keys ipf\", got {:?}",
+                            number + 1,
+                            line
+                        ),
+                    ))
+                }
+            }
+        }
+
+        frames.sort_by_key(|&(frame, _, _)| frame);
+        Ok(Self { frames })
+    }
+
+    // the keys and ipf recorded for a frame, None for frames that weren't recorded
+    pub fn frame(&self, frame: u64) -> Option<([bool; 16], usize)> {
+        let index = self
+            .frames
+            .binary_search_by_key(&frame, |&(frame, _, _)| frame)
+            .ok()?;
+        let (_, mask, ipf) = self.frames[index];
+
+        Some((keypad_from_mask(mask), ipf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaying_a_recording_reaches_the_same_state() {
+        use chip8::{rng::SeededRng, CHIP8};
+
+        // RND V0, FF; SKNP V5 (key 0); ADD V1, 1; JP 200
+        let rom = [0xC0, 0xFF, 0xE5, 0xA1, 0x71, 0x01, 0x12, 0x00];
+        let machine = || {
+            let mut chip8 = CHIP8::new();
+            chip8.set_rng(Box::new(SeededRng::new(0)));
+            chip8.load_rom_bytes(&rom).unwrap();
+            chip8
+        };
+        let path = std::env::temp_dir().join(format!("chip8-recording-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut recorded = machine();
+        let mut recorder = Recorder::create(path).unwrap();
+        for frame in 0..30 {
+            recorded.keypad[0] = (5..10).contains(&frame) || frame == 20;
+            let ipf = if frame < 15 { 10 } else { 25 };
+            recorder.record(frame, &recorded.keypad, ipf).unwrap();
+            recorded.run_frame(ipf).unwrap();
+        }
+        drop(recorder);
+
+        let replay = Replay::open(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let mut replayed = machine();
+        for frame in 0..30 {
+            let (keys, ipf) = replay.frame(frame).unwrap();
+            replayed.keypad = keys;
+            replayed.run_frame(ipf).unwrap();
+        }
+
+        assert!(replay.frame(30).is_none());
+        assert_ne!(recorded.vregister[1], 0);
+        assert!(recorded == replayed);
+    }
+
+    #[test]
+    fn keypad_masks_round_trip() {
+        let mut keypad = [false; 16];
+        keypad[5] = true;
+        keypad[0xF] = true;
+        assert_eq!(keypad_mask(&keypad), 0x8020);
+        assert_eq!(keypad_from_mask(0x8020), keypad);
+    }
+}
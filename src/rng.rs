@@ -0,0 +1,201 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+
+// source of the random bytes used by Cxkk, swap it out for deterministic runs
+pub trait Rng: Send {
+    fn next_byte(&mut self) -> u8;
+    fn box_clone(&self) -> Box<dyn Rng>;
+}
+
+impl Clone for Box<dyn Rng> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+// the rng as a machine holds it, an rng's state can't be compared without drawing from it
+// so any two count as equal (this lets machines derive PartialEq whatever their rng is)
+#[derive(Clone)]
+pub(crate) struct MachineRng(pub(crate) Box<dyn Rng>);
+
+impl Default for MachineRng {
+    fn default() -> Self {
+        Self(default_rng())
+    }
+}
+
+impl PartialEq for MachineRng {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+// the default with std, random bytes from the rand crate's thread rng
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Default)]
+pub struct ThreadRng;
+
+#[cfg(feature = "std")]
+impl Rng for ThreadRng {
+    fn next_byte(&mut self) -> u8 {
+        rand::random()
+    }
+
+    fn box_clone(&self) -> Box<dyn Rng> {
+        Box::new(*self)
+    }
+}
+
+// xorshift64* generator, the same seed always gives the same bytes
+#[derive(Clone, Copy)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on a zero state
+        Self {
+            state: if seed == 0 { 0x9E3779B97F4A7C15 } else { seed },
+        }
+    }
+}
+
+impl Rng for SeededRng {
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        (self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 56) as u8
+    }
+
+    fn box_clone(&self) -> Box<dyn Rng> {
+        Box::new(*self)
+    }
+}
+
+// passes another rng's bytes through and keeps a copy of each, so a run using Cxkk can be replayed
+// with ScriptedRng. clones share the same log
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct RecordingRng {
+    inner: Box<dyn Rng>,
+    log: Arc<Mutex<Vec<u8>>>,
+}
+
+#[cfg(feature = "std")]
+impl RecordingRng {
+    pub fn new(inner: Box<dyn Rng>) -> Self {
+        Self {
+            inner,
+            log: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    // the bytes handed out, this keeps working after the rng has been given to a machine
+    pub fn log(&self) -> Arc<Mutex<Vec<u8>>> {
+        Arc::clone(&self.log)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Rng for RecordingRng {
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.inner.next_byte();
+        self.log
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(byte);
+        byte
+    }
+
+    fn box_clone(&self) -> Box<dyn Rng> {
+        Box::new(self.clone())
+    }
+}
+
+// hands out a fixed list of bytes in order, e.g. ones logged by RecordingRng
+// panics once they run out, a replay asking for more than was recorded has already diverged
+#[derive(Clone)]
+pub struct ScriptedRng {
+    bytes: Vec<u8>,
+    next: usize,
+}
+
+impl ScriptedRng {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes, next: 0 }
+    }
+
+    // how many bytes haven't been used yet
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.next
+    }
+}
+
+impl Rng for ScriptedRng {
+    fn next_byte(&mut self) -> u8 {
+        let Some(&byte) = self.bytes.get(self.next) else {
+            panic!("ScriptedRng ran out after {} bytes", self.bytes.len());
+        };
+        self.next += 1;
+        byte
+    }
+
+    fn box_clone(&self) -> Box<dyn Rng> {
+        Box::new(self.clone())
+    }
+}
+
+// ThreadRng with std, a fixed seed without it (there's no entropy source to draw from)
+pub(crate) fn default_rng() -> Box<dyn Rng> {
+    #[cfg(feature = "std")]
+    return Box::new(ThreadRng);
+
+    #[cfg(not(feature = "std"))]
+    return Box::new(SeededRng::new(0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn recorded_bytes_replay_the_same_run() {
+        use crate::CHIP8;
+
+        // RND V0, FF; RND V1, 0F; I = 300; LD [I], V1; JP 208
+        let rom = [0xC0, 0xFF, 0xC1, 0x0F, 0xA3, 0x00, 0xF1, 0x55, 0x12, 0x08];
+        let run = |rng: Box<dyn Rng>| {
+            let mut chip8 = CHIP8::builder().rng(rng).build();
+            chip8.load_rom_bytes(&rom).unwrap();
+            while !chip8.is_halted() {
+                chip8.cycle().unwrap();
+            }
+            chip8
+        };
+
+        let recording = RecordingRng::new(Box::new(ThreadRng));
+        let log = recording.log();
+        let recorded = run(Box::new(recording));
+        let bytes = log.lock().unwrap().clone();
+        assert_eq!(bytes.len(), 2);
+        assert_eq!(recorded.memory[0x300..0x302], [bytes[0], bytes[1] & 0x0F]);
+
+        let replayed = run(Box::new(ScriptedRng::new(bytes)));
+        assert!(replayed == recorded);
+    }
+
+    #[test]
+    #[should_panic(expected = "ScriptedRng ran out after 2 bytes")]
+    fn scripted_rng_panics_once_it_runs_out() {
+        let mut rng = ScriptedRng::new(alloc::vec![1, 2]);
+        assert_eq!((rng.next_byte(), rng.next_byte()), (1, 2));
+        assert_eq!(rng.remaining(), 0);
+        rng.next_byte();
+    }
+}
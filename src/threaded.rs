@@ -0,0 +1,195 @@
+// run the machine on its own thread and talk to it with messages, for UIs that can't block on it
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::chip8::{RomError, StepError, CHIP8};
+
+const FRAME_TIME: Duration = Duration::from_micros(16_667); // 60Hz
+
+// a copy of the display, SUPER-CHIP programs can change its size
+#[derive(Clone)]
+pub struct Framebuffer {
+    pub pixels: Vec<u8>, // width * height, row by row like CHIP8::display
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Framebuffer {
+    fn of(chip8: &CHIP8) -> Self {
+        Self {
+            pixels: chip8.display.clone(),
+            width: chip8.display_width(),
+            height: chip8.display_height(),
+        }
+    }
+}
+
+pub enum Command {
+    Pause,
+    Resume,
+    Reset,              // start the current rom over
+    PressKey(u8, bool), // a keypad key went down (true) or up (false)
+    LoadRom(Vec<u8>),   // reset and run a different rom
+    Step,               // run one instruction, only does anything while paused
+    Stop,               // end the thread, ThreadHandle::stop hands the machine back
+}
+
+// why the thread stopped on its own
+#[derive(Debug)]
+pub enum ThreadError {
+    Step(StepError),
+    Rom(RomError), // a LoadRom rom didn't fit
+}
+
+pub struct ThreadHandle {
+    commands: mpsc::Sender<Command>,
+    framebuffer: Arc<Mutex<Framebuffer>>,
+    thread: JoinHandle<Result<CHIP8, ThreadError>>,
+}
+
+impl ThreadHandle {
+    // false once the thread has stopped, e.g. because the program failed
+    pub fn send(&self, command: Command) -> bool {
+        self.commands.send(command).is_ok()
+    }
+
+    // the display as of the last frame the thread ran
+    pub fn framebuffer(&self) -> Framebuffer {
+        self.framebuffer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    // stop the thread and get the machine back, or the error that stopped it earlier
+    pub fn stop(self) -> Result<CHIP8, ThreadError> {
+        let _ = self.commands.send(Command::Stop);
+        self.thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+// run an already loaded machine at `ipf` instructions per 60Hz frame on a new thread
+// Reset reloads the rom that was loaded when the thread started (or the last LoadRom)
+pub fn run_threaded(mut chip8: CHIP8, ipf: usize) -> ThreadHandle {
+    let (commands, receiver) = mpsc::channel();
+    let framebuffer = Arc::new(Mutex::new(Framebuffer::of(&chip8)));
+    let shared = Arc::clone(&framebuffer);
+
+    let thread = thread::spawn(move || {
+        let mut rom = chip8.rom().to_vec();
+        let mut paused = false;
+        let mut next_frame = Instant::now();
+
+        loop {
+            // while paused there's nothing to do until a command comes in
+            let command = if paused {
+                match receiver.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => return Ok(chip8), // the handle was dropped
+                }
+            } else {
+                match receiver.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(mpsc::TryRecvError::Empty) => None,
+                    Err(mpsc::TryRecvError::Disconnected) => return Ok(chip8),
+                }
+            };
+
+            match command {
+                Some(Command::Pause) => paused = true,
+                Some(Command::Resume) => {
+                    paused = false;
+                    next_frame = Instant::now();
+                }
+                Some(Command::Reset) => restart(&mut chip8, &rom).map_err(ThreadError::Rom)?,
+                Some(Command::PressKey(key, pressed)) => {
+                    if let Some(held) = chip8.keypad.get_mut(key as usize) {
+                        *held = pressed;
+                    }
+                }
+                Some(Command::LoadRom(data)) => {
+                    restart(&mut chip8, &data).map_err(ThreadError::Rom)?;
+                    rom = data;
+                }
+                Some(Command::Step) if paused => {
+                    chip8.cycle().map_err(ThreadError::Step)?;
+                }
+                Some(Command::Step) => {}
+                Some(Command::Stop) => return Ok(chip8),
+                // no more commands, run the frame that's due
+                None => {
+                    chip8.run_frame(ipf).map_err(ThreadError::Step)?;
+                    next_frame += FRAME_TIME;
+
+                    let now = Instant::now();
+                    if next_frame > now {
+                        thread::sleep(next_frame - now);
+                    } else {
+                        next_frame = now; // fell behind, don't try to catch up
+                    }
+                }
+            }
+
+            *shared.lock().unwrap_or_else(|e| e.into_inner()) = Framebuffer::of(&chip8);
+        }
+    });
+
+    ThreadHandle {
+        commands,
+        framebuffer,
+        thread,
+    }
+}
+
+fn restart(chip8: &mut CHIP8, rom: &[u8]) -> Result<(), RomError> {
+    chip8.reset();
+    chip8.load_fonts();
+    chip8.load_rom_bytes(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_thread_runs_commands_in_order() {
+        let mut chip8 = CHIP8::new();
+        chip8.load_fonts();
+        // ADD V0, 1; LD F, V0; DRW V0, V0, 5; JP 200
+        chip8
+            .load_rom_bytes(&[0x70, 0x01, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x00])
+            .unwrap();
+        let handle = run_threaded(chip8, 10);
+
+        assert!(handle.send(Command::Pause));
+        for _ in 0..3 {
+            assert!(handle.send(Command::Step));
+        }
+        assert!(handle.send(Command::PressKey(3, true)));
+        assert!(handle.send(Command::PressKey(16, true))); // not a key, ignored
+
+        // the framebuffer is shared after every command, so the draw shows up soon
+        let start = Instant::now();
+        while !handle.framebuffer().pixels.contains(&1) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "nothing was drawn"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let chip8 = handle.stop().unwrap();
+        assert!(chip8.instructions_executed() >= 3);
+        assert!(chip8.keypad[3]);
+    }
+
+    #[test]
+    fn a_rom_that_doesnt_fit_stops_the_thread() {
+        let handle = run_threaded(CHIP8::new(), 10);
+        handle.send(Command::LoadRom(vec![0; 5000]));
+        assert!(matches!(handle.stop(), Err(ThreadError::Rom(_))));
+    }
+}
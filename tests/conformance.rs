@@ -0,0 +1,46 @@
+// test roms run headless against the frames they're known to leave on the screen
+// to add one, put the rom next to test_opcode.ch8, its final frame in tests/expected (one line per
+// row, '#' on and '.' off, what render_ascii_with('#', '.') prints) and an entry in CONFORMANCE_ROMS
+use chip8::{frontend::DEFAULT_INSTRUCTIONS_PER_FRAME, Quirks, CHIP8};
+
+struct ConformanceRom {
+    name: &'static str,
+    rom: &'static [u8],
+    quirks: fn() -> Quirks,
+    frames: usize, // enough 60Hz frames for the rom to finish drawing
+    expected: &'static str,
+}
+
+const CONFORMANCE_ROMS: [ConformanceRom; 1] = [ConformanceRom {
+    // corax+'s opcode test, every result on the screen should read OK
+    name: "test_opcode",
+    rom: include_bytes!("../test_opcode.ch8"),
+    quirks: Quirks::cosmac_vip,
+    frames: 300,
+    expected: include_str!("expected/test_opcode.txt"),
+}];
+
+// the ascii frame as display pixels
+fn parse_frame(frame: &str) -> Vec<u8> {
+    frame
+        .lines()
+        .flat_map(|line| line.chars().map(|c| (c == '#') as u8))
+        .collect()
+}
+
+#[test]
+fn conformance_roms_draw_their_expected_frames() {
+    for test in &CONFORMANCE_ROMS {
+        let mut chip8 = CHIP8::builder().quirks((test.quirks)()).build();
+        chip8.load_fonts();
+        chip8.load_rom_bytes(test.rom).unwrap();
+
+        for _ in 0..test.frames {
+            chip8.run_frame(DEFAULT_INSTRUCTIONS_PER_FRAME).unwrap();
+        }
+
+        if let Some(diff) = chip8.display_diff(&parse_frame(test.expected)) {
+            panic!("{} drew the wrong frame\n{}", test.name, diff);
+        }
+    }
+}